@@ -52,16 +52,16 @@ function NavBar() {
 
   const handleSearchKeyDown = (event: { key: string; }) => {
     if (event.key === 'Enter') {
-      navigate(`/search/${searchString}`);
+      navigate(`/search/${encodeURIComponent(searchString)}`);
     }
   };
 
   const handleSearchClick = () => {
-    navigate(`/search/${searchString}`);
+    navigate(`/search/${encodeURIComponent(searchString)}`);
   };
 
   const handleExactSearchClick = () => {
-    navigate(`/search/${searchString}/exact`);
+    navigate(`/search/${encodeURIComponent(searchString)}/exact`);
   };
 
   useEffect(() => {
@@ -96,15 +96,13 @@ function NavBar() {
     const checkHealth = async () => {
       try {
         const response = await fetch(API_ENDPOINT + '/health');
-        const message = await response.text();
-        if (!response.ok) {
-          setHealthMessage(message || 'API health check failed.'); // Set message if not OK
+        const report = await response.json();
+        if (report.status === "ok") {
+          setHealthMessage(null);
+        } else if (report.message.startsWith("Daily Update Running.")) {
+          setHealthMessage("Daily Update Running. Match data may be delayed.");
         } else {
-          if (message.startsWith("Daily Update Running.")) {
-            setHealthMessage("Daily Update Running. Match data may be delayed.");
-          } else {
-            setHealthMessage(null);
-          }
+          setHealthMessage(report.message || 'API health check failed.'); // Set message if not OK
         }
       } catch (error) {
         console.error('Error fetching health status:', error);
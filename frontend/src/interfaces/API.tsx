@@ -17,6 +17,14 @@ export interface PlayerResponsePlayer {
   top_char: number;
   top_defeated: TopDefeated;
   top_rating: TopRating;
+  official?: OfficialStats | null; // Totals from the game's own stats, once the player has been synced
+}
+
+export interface OfficialStats {
+  level: number | null;
+  ranked_games: number | null;
+  ranked_wins: number | null;
+  updated_at: string;
 }
 
 export interface TopDefeated {
@@ -84,8 +92,17 @@ export interface PlayerSearchResponse {
   name: string; // Player's name
   rating: number; // Player's rating
   deviation: number; // Player's rating deviation
-  char_short: string; // Short name of the character
-  char_long: string; // Full name of the character
+  char_short: string; // Short name of the best rated character
+  char_long: string; // Full name of the best rated character
+  matched_name: string | null; // The old name that matched, if not the current one
+  characters: SearchCharacterResponse[]; // Best rated characters first
+  tags: TagResponse[];
+}
+
+export interface SearchCharacterResponse {
+  rating: number;
+  char_short: string;
+  char_long: string;
 }
 
 export interface SettingsResponse {
@@ -252,9 +252,35 @@ const Player = () => {
     
     try {
       const response = await fetch(`${API_ENDPOINT}/rating_sync/${player_id_checked}`);
-      const result = await response.text();
-      
-      if (response.ok) {
+      let result = await response.text();
+      let synced = response.ok;
+
+      // The sync is queued, poll until the pull process has done it
+      if (synced) {
+        synced = false;
+        result = 'Rating sync timed out, try again later';
+
+        for (let attempt = 0; attempt < 30; attempt++) {
+          await new Promise(resolve => setTimeout(resolve, 2000));
+
+          const status_response = await fetch(`${API_ENDPOINT}/rating_sync/${player_id_checked}/status`);
+          if (!status_response.ok) {
+            continue;
+          }
+
+          const status = await status_response.json();
+          if (status.status === 'done') {
+            synced = true;
+            break;
+          }
+          if (status.status === 'failed') {
+            result = status.message;
+            break;
+          }
+        }
+      }
+
+      if (synced) {
         // Fetch updated player data instead of reloading page
         const player_response = await fetch(API_ENDPOINT + '/player/' + player_id_checked);
         const player_result = await player_response.text().then(body => {
@@ -450,6 +476,14 @@ const Player = () => {
                       </Typography>
                     </React.Fragment>
                   ) : null}
+                  {currentCharData.official ? (
+                    <Typography>
+                      Official Stats: {currentCharData.official.level !== null ? `Level ${currentCharData.official.level}` : null}
+                      {currentCharData.official.ranked_games !== null ? ` · ${currentCharData.official.ranked_games} ranked games` : null}
+                      {currentCharData.official.ranked_wins !== null ? ` · ${currentCharData.official.ranked_wins} wins` : null}
+                      {' '}({Utils.formatUTCToLocal(currentCharData.official.updated_at)})
+                    </Typography>
+                  ) : null}
                   {currentCharData.top_defeated.value !== 0.0 ? (
                     <Typography>
                       Top Defeated: <Button sx={{ fontSize: '16px' }} component={Link} onMouseDown={(event) => onLinkClick(event, `/player/${currentCharData.top_defeated.id}/${currentCharData.top_defeated.char_short}`)}>{currentCharData.top_defeated.name} ({currentCharData.top_defeated.char_short})</Button> <Box component={"span"}>{Utils.displayRating(currentCharData.top_defeated.value)}</Box> ({Utils.formatUTCToLocal(currentCharData.top_defeated.timestamp)})
@@ -569,6 +603,14 @@ const Player = () => {
                     </React.Fragment>
                   ) : null}
 
+                  {currentCharData.official ? (
+                    <Typography>
+                      Official Stats: {currentCharData.official.level !== null ? `Level ${currentCharData.official.level}` : null}
+                      {currentCharData.official.ranked_games !== null ? ` · ${currentCharData.official.ranked_games} ranked games` : null}
+                      {currentCharData.official.ranked_wins !== null ? ` · ${currentCharData.official.ranked_wins} wins` : null}
+                      {' '}({Utils.formatUTCToLocal(currentCharData.official.updated_at)})
+                    </Typography>
+                  ) : null}
                   {currentCharData.top_defeated.value !== 0.0 ? (
                     <Typography>
                       Top Defeated: <Button sx={{ fontSize: '16px' }} component={Link} onMouseDown={(event) => onLinkClick(event, `/player/${currentCharData.top_defeated.id}/${currentCharData.top_defeated.char_short}`)}>{currentCharData.top_defeated.name} ({currentCharData.top_defeated.char_short})</Button> <Box component={"span"}>{Utils.displayRating(currentCharData.top_defeated.value)}</Box> ({Utils.formatUTCToLocal(currentCharData.top_defeated.timestamp)})
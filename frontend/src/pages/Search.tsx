@@ -11,6 +11,7 @@ import TableRow from '@mui/material/TableRow';
 import Typography from '@mui/material/Typography';
 import React, { useEffect, useState } from 'react';
 import { Link, useNavigate, useParams } from 'react-router-dom';
+import { Tag } from "./../components/Tag";
 import { PlayerSearchResponse } from '../interfaces/API';
 import { JSONParse } from '../utils/JSONParse';
 import { Utils } from '../utils/Utils';
@@ -26,6 +27,8 @@ const Search = () => {
 
   const [loading, setLoading ] = useState(true);
 
+  const [error, setError] = useState<string | null>(null);
+
   useEffect(() => {
     document.title = 'Search Results | Puddle Farm';
     window.scrollTo(0, 0);
@@ -35,10 +38,18 @@ const Search = () => {
       try {
         const url = API_ENDPOINT
           + '/player/search?'
-          + 'search_string=' + search_string
-          + '&exact=' + ((exact && exact === 'exact') ? 'true' : 'false');
+          + 'search_string=' + encodeURIComponent(search_string ?? '')
+          + '&mode=' + ((exact && exact === 'exact') ? 'exact' : 'contains');
         const response = await fetch(url);
 
+        if (response.status === 400) {
+          setError(await response.text());
+          setResults([]);
+          setLoading(false);
+          return;
+        }
+        setError(null);
+
         // eslint-disable-next-line
         const result = await response.text().then(body => {
           
@@ -80,6 +91,9 @@ const Search = () => {
         </Box>
       </AppBar>
       <Box m={4} maxWidth="700px">
+        {error ? (
+          <Typography gutterBottom>{error}</Typography>
+        ) : null}
         <TableContainer component={Paper}>
           <Table size="small">
             <TableHead>
@@ -92,8 +106,18 @@ const Search = () => {
             <TableBody>
               {results.map((player, index) => (
                 <TableRow key={index}>
-                  <TableCell><Button component={Link} to={`/player/${player.id}/${player.char_short}`}>{player.name}</Button></TableCell>
-                  <TableCell>{player.char_short}</TableCell>
+                  <TableCell>
+                    <Button component={Link} to={`/player/${player.id}/${player.char_short}`}>{player.name}</Button>
+                    {player.tags.map((e, i) => (
+                      <Tag key={i} style={JSON.parse(e.style)} sx={{ fontSize: '0.9rem', position: 'unset' }}>
+                        {e.tag}
+                      </Tag>
+                    ))}
+                    {player.matched_name ? (
+                      <Typography variant="body2">Formerly {player.matched_name}</Typography>
+                    ) : null}
+                  </TableCell>
+                  <TableCell>{player.characters.map((c) => c.char_short).join(', ')}</TableCell>
                   <TableCell>
                     {Utils.displayRankIcon(player.rating, "32px")}
                     <Box component={'span'}>{Utils.displayRating(player.rating)}</Box>
@@ -52,19 +52,12 @@ const Stats = () => {
 
         const health_response = await fetch(health_url);
 
-        if (health_response.status === 200) {
-          const result = await health_response.text().then(body => {
-            return body;
-          });
+        const health_report = await health_response.json();
 
-          setHealth(result);
-
-        } else if (health_response.status === 500) {
-          const result = await health_response.text().then(body => {
-            return body;
-          });
-
-          setHealth("Error! " + result);
+        if (health_report.status === "down") {
+          setHealth("Error! " + health_report.message);
+        } else {
+          setHealth(health_report.message);
         }
 
 
@@ -0,0 +1,2 @@
+DROP INDEX player_ratings_char_value;
+DROP TABLE rank_tiers;
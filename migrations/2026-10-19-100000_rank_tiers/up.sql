@@ -0,0 +1,30 @@
+CREATE TABLE rank_tiers (
+    lower_bound INT NOT NULL PRIMARY KEY,
+    upper_bound INT NOT NULL,
+    name TEXT NOT NULL
+);
+
+-- Tiers with an upper bound of 1 or less are treated as placement (unranked).
+INSERT INTO rank_tiers (lower_bound, upper_bound, name) VALUES
+    (-10000000, 1, 'Placement'),
+    (1, 1000, 'Iron 1'),
+    (1000, 2000, 'Iron 2'),
+    (2000, 3000, 'Iron 3'),
+    (3000, 4200, 'Bronze 1'),
+    (4200, 5400, 'Bronze 2'),
+    (5400, 6600, 'Bronze 3'),
+    (6600, 8800, 'Silver 1'),
+    (8800, 11000, 'Silver 2'),
+    (11000, 13200, 'Silver 3'),
+    (13200, 15600, 'Gold 1'),
+    (15600, 18000, 'Gold 2'),
+    (18000, 20400, 'Gold 3'),
+    (20400, 24400, 'Platinum 1'),
+    (24400, 28400, 'Platinum 2'),
+    (28400, 32400, 'Platinum 3'),
+    (32400, 36600, 'Diamond 1'),
+    (36600, 40800, 'Diamond 2'),
+    (40800, 45000, 'Diamond 3'),
+    (45000, 200000000, 'Vanquisher');
+
+CREATE INDEX player_ratings_char_value ON player_ratings (char_id, value);
@@ -0,0 +1 @@
+DROP TABLE character_daily_stats;
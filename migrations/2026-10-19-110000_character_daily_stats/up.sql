@@ -0,0 +1,7 @@
+CREATE TABLE character_daily_stats (
+    day DATE NOT NULL,
+    char_id SMALLINT NOT NULL,
+    games BIGINT NOT NULL,
+    players BIGINT NOT NULL,
+    PRIMARY KEY (day, char_id)
+);
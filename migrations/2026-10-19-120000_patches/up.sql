@@ -0,0 +1,8 @@
+CREATE TABLE patches (
+    version TEXT NOT NULL PRIMARY KEY,
+    start_date TIMESTAMP NOT NULL,
+    label TEXT NOT NULL
+);
+CREATE INDEX patches_start_date ON patches (start_date);
+
+ALTER TABLE games ADD COLUMN version TEXT;
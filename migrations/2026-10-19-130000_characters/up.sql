@@ -0,0 +1,41 @@
+CREATE TABLE characters (
+    id SMALLINT NOT NULL PRIMARY KEY,
+    short TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    json_code TEXT NOT NULL,
+    release_date DATE
+);
+
+INSERT INTO characters (id, short, name, json_code) VALUES
+    (0, 'SO', 'Sol', 'SOL'),
+    (1, 'KY', 'Ky', 'KYK'),
+    (2, 'MA', 'May', 'MAY'),
+    (3, 'AX', 'Axl', 'AXL'),
+    (4, 'CH', 'Chipp', 'CHP'),
+    (5, 'PO', 'Potemkin', 'POT'),
+    (6, 'FA', 'Faust', 'FAU'),
+    (7, 'MI', 'Millia', 'MLL'),
+    (8, 'ZA', 'Zato-1', 'ZAT'),
+    (9, 'RA', 'Ramlethal', 'RAM'),
+    (10, 'LE', 'Leo', 'LEO'),
+    (11, 'NA', 'Nagoriyuki', 'NAG'),
+    (12, 'GI', 'Giovanna', 'GIO'),
+    (13, 'AN', 'Anji', 'ANJ'),
+    (14, 'IN', 'I-No', 'INO'),
+    (15, 'GO', 'Goldlewis', 'GLD'),
+    (16, 'JC', 'Jack-O''', 'JKO'),
+    (17, 'HA', 'Happy Chaos', 'COS'),
+    (18, 'BA', 'Baiken', 'BKN'),
+    (19, 'TE', 'Testament', 'TST'),
+    (20, 'BI', 'Bridget', 'BGT'),
+    (21, 'SI', 'Sin', 'SIN'),
+    (22, 'BE', 'Bedman?', 'BED'),
+    (23, 'AS', 'Asuka', 'ASK'),
+    (24, 'JN', 'Johnny', 'JHN'),
+    (25, 'EL', 'Elphelt', 'ELP'),
+    (26, 'AB', 'A.B.A.', 'ABA'),
+    (27, 'SL', 'Slayer', 'SLY'),
+    (28, 'DI', 'Dizzy', 'DZY'),
+    (29, 'VE', 'Venom', 'VEN'),
+    (30, 'UN', 'Unika', 'UNI'),
+    (31, 'LU', 'Lucy', 'LUC');
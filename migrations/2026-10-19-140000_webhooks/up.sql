@@ -0,0 +1,12 @@
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    owner_id BIGINT NOT NULL REFERENCES players(id),
+    target_id BIGINT NOT NULL REFERENCES players(id),
+    url TEXT NOT NULL,
+    on_game BOOLEAN NOT NULL DEFAULT false,
+    on_rating_high BOOLEAN NOT NULL DEFAULT false,
+    on_top_100 BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+CREATE INDEX webhooks_owner_id ON webhooks (owner_id);
+CREATE INDEX webhooks_target_id ON webhooks (target_id);
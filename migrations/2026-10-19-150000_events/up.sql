@@ -0,0 +1,11 @@
+CREATE TABLE events (
+    id BIGSERIAL PRIMARY KEY,
+    player_id BIGINT NOT NULL REFERENCES players(id),
+    char_id SMALLINT NOT NULL,
+    kind TEXT NOT NULL,
+    value BIGINT NOT NULL,
+    previous_value BIGINT,
+    tier TEXT,
+    timestamp TIMESTAMP NOT NULL
+);
+CREATE INDEX events_player_id_timestamp ON events (player_id, timestamp DESC);
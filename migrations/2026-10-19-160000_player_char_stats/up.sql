@@ -0,0 +1,18 @@
+-- Filled in as games are pulled, run `cargo run backfill` once to backfill existing games.
+CREATE TABLE player_char_stats (
+    id BIGINT NOT NULL REFERENCES players(id),
+    char_id SMALLINT NOT NULL,
+    games BIGINT NOT NULL,
+    wins BIGINT NOT NULL,
+    losses BIGINT NOT NULL,
+    peak_rating BIGINT NOT NULL,
+    peak_timestamp TIMESTAMP NOT NULL,
+    top_defeated_id BIGINT,
+    top_defeated_name TEXT,
+    top_defeated_char SMALLINT,
+    top_defeated_value BIGINT,
+    top_defeated_timestamp TIMESTAMP,
+    first_played TIMESTAMP NOT NULL,
+    last_played TIMESTAMP NOT NULL,
+    PRIMARY KEY (id, char_id)
+);
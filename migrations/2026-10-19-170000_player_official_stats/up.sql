@@ -0,0 +1,10 @@
+-- Official per-character totals from the GGST statistics api, refreshed on every rating sync.
+CREATE TABLE player_official_stats (
+    id BIGINT NOT NULL REFERENCES players(id),
+    char_id SMALLINT NOT NULL,
+    level INTEGER,
+    ranked_games INTEGER,
+    ranked_wins INTEGER,
+    updated_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (id, char_id)
+);
@@ -0,0 +1,5 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Trigram indexes serve both the fuzzy % match and ILIKE, so searches don't scan every name
+CREATE INDEX players_name_trgm ON players USING gin (name gin_trgm_ops);
+CREATE INDEX player_names_name_trgm ON player_names USING gin (name gin_trgm_ops);
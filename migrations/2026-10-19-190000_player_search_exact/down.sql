@@ -0,0 +1,2 @@
+DROP INDEX player_names_name_lower;
+DROP INDEX players_name_lower;
@@ -0,0 +1,3 @@
+-- Exact searches compare lower(name), which the trigram indexes can't serve
+CREATE INDEX players_name_lower ON players (lower(name));
+CREATE INDEX player_names_name_lower ON player_names (lower(name));
@@ -0,0 +1 @@
+DROP INDEX games_version;
@@ -0,0 +1,2 @@
+-- Patch scoped stats, matchups, popularity and player queries filter on the recorded version
+CREATE INDEX games_version ON games (version, timestamp);
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use tracing::warn;
+
+use crate::models::Character;
+use crate::schema;
+
+lazy_static! {
+    static ref CHARACTERS: RwLock<Arc<Vec<Character>>> = RwLock::new(Arc::new(Vec::new()));
+}
+
+/// Every registered character, ordered by id.
+pub fn all() -> Arc<Vec<Character>> {
+    CHARACTERS.read().unwrap().clone()
+}
+
+/// The character with this id, or a placeholder if the registry doesn't know it yet.
+pub fn get(id: i16) -> Character {
+    match all().iter().find(|c| c.id == id) {
+        Some(c) => c.clone(),
+        None => unknown(id),
+    }
+}
+
+/// Id of the character with this short code.
+pub fn find(short: &str) -> Option<i16> {
+    all().iter().find(|c| c.short == short).map(|c| c.id)
+}
+
+/// Stand-in for a character id that shows up in replays before it's in the registry.
+fn unknown(id: i16) -> Character {
+    Character {
+        id,
+        short: format!("U{}", id),
+        name: format!("Unknown ({})", id),
+        json_code: String::new(),
+        release_date: None,
+    }
+}
+
+fn set(mut characters: Vec<Character>) {
+    characters.sort_by_key(|c| c.id);
+    *CHARACTERS.write().unwrap() = Arc::new(characters);
+}
+
+pub async fn load(conn: &mut AsyncPgConnection) -> Result<(), String> {
+    match schema::characters::table
+        .select(Character::as_select())
+        .load(conn)
+        .await
+    {
+        Ok(characters) => {
+            set(characters);
+            Ok(())
+        }
+        Err(e) => Err(format!("Failed to load characters: {e}")),
+    }
+}
+
+/// Registers placeholders for ids missing from the registry, first seen today.
+/// Their names and codes are meant to be filled in by hand afterwards.
+pub async fn register_unknown(
+    ids: &HashSet<i16>,
+    conn: &mut AsyncPgConnection,
+) -> Result<(), String> {
+    let registry = all();
+
+    let missing: Vec<Character> = ids
+        .iter()
+        .filter(|id| !registry.iter().any(|c| c.id == **id))
+        .map(|id| Character {
+            release_date: Some(chrono::Utc::now().date_naive()),
+            ..unknown(*id)
+        })
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    for c in &missing {
+        warn!("Registering unknown character id {}", c.id);
+    }
+
+    if let Err(e) = diesel::insert_into(schema::characters::table)
+        .values(&missing)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await
+    {
+        return Err(format!("Failed to register characters: {e}"));
+    }
+
+    load(conn).await
+}
+
+#[cfg(test)]
+pub fn set_test_characters() {
+    set(vec![
+        Character {
+            id: 0,
+            short: "SO".to_string(),
+            name: "Sol".to_string(),
+            json_code: "SOL".to_string(),
+            release_date: None,
+        },
+        Character {
+            id: 1,
+            short: "KY".to_string(),
+            name: "Ky".to_string(),
+            json_code: "KYK".to_string(),
+            release_date: None,
+        },
+        Character {
+            id: 2,
+            short: "MA".to_string(),
+            name: "May".to_string(),
+            json_code: "MAY".to_string(),
+            release_date: None,
+        },
+    ]);
+}
@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use tracing::info;
+
+use crate::{ggst_api, imdb, pull};
+
+#[derive(Parser)]
+#[command(
+    name = "puddle-farm",
+    about = "Guilty Gear Strive rating tracker",
+    version
+)]
+pub struct Cli {
+    /// Config file to use instead of puddle-farm.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Show what a command would change without writing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Defaults to `serve`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Run the web server
+    Serve,
+    /// Run the timed jobs continuously: grab replays, update ratings, rankings, redis, etc.
+    Pull,
+    /// Run the hourly jobs once
+    Hourly,
+    /// Run the daily jobs once
+    Daily,
+    /// Apply pending database migrations
+    Migrate,
+    /// Recompute the per-character player stats from every stored game
+    Backfill,
+    /// Recompute the global and per-character rankings
+    RebuildRanks,
+    /// Update a player's ratings from their in-game stats
+    SyncPlayer { player_id: i64 },
+    /// Download a player's avatar into the cache
+    FetchAvatar { player_id: i64 },
+    /// Log the webhooks received, to try out webhook subscriptions locally
+    WebhookReceiver {
+        #[arg(default_value = "127.0.0.1:8002")]
+        addr: String,
+    },
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Serve => "serve",
+            Command::Pull => "pull",
+            Command::Hourly => "hourly",
+            Command::Daily => "daily",
+            Command::Migrate => "migrate",
+            Command::Backfill => "backfill",
+            Command::RebuildRanks => "rebuild-ranks",
+            Command::SyncPlayer { .. } => "sync-player",
+            Command::FetchAvatar { .. } => "fetch-avatar",
+            Command::WebhookReceiver { .. } => "webhook-receiver",
+        }
+    }
+
+    /// The GGST account settings are only required by these, serve refreshes avatars.
+    pub fn uses_ggst(&self) -> bool {
+        matches!(
+            self,
+            Command::Serve
+                | Command::Pull
+                | Command::SyncPlayer { .. }
+                | Command::FetchAvatar { .. }
+        )
+    }
+
+    /// Hourly and daily also write to Redis, which can't be rolled back.
+    pub fn supports_dry_run(&self) -> bool {
+        matches!(
+            self,
+            Command::Migrate
+                | Command::Backfill
+                | Command::RebuildRanks
+                | Command::SyncPlayer { .. }
+                | Command::FetchAvatar { .. }
+        )
+    }
+}
+
+fn check_ggst_connected() -> Result<(), String> {
+    if !std::fs::exists("token.txt").unwrap_or(false) {
+        return Err("GGST is not connected, token.txt not found".to_string());
+    }
+
+    Ok(())
+}
+
+pub async fn sync_player(
+    state: crate::AppState,
+    player_id: i64,
+    dry_run: bool,
+) -> Result<(), String> {
+    let mut db = match state.db_pool.get().await {
+        Ok(db) => db,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if dry_run {
+        let diff = pull::preview_player_sync(player_id, &mut db).await?;
+
+        println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+        return Ok(());
+    }
+
+    let mut redis = match state.redis_pool.get().await {
+        Ok(redis) => redis,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let diff = pull::sync_player_ratings(player_id, &mut db, &mut redis).await?;
+
+    println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+    Ok(())
+}
+
+/// Replaces the cached avatar, even if it hasn't expired yet.
+pub async fn fetch_avatar(
+    state: crate::AppState,
+    player_id: i64,
+    dry_run: bool,
+) -> Result<(), String> {
+    check_ggst_connected()?;
+
+    let png = ggst_api::get_player_avatar(player_id.to_string()).await?;
+
+    if dry_run {
+        info!("Fetched a {} byte avatar", png.len());
+        return Ok(());
+    }
+
+    let mut redis = match state.redis_pool.get().await {
+        Ok(redis) => redis,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    imdb::set_avatar(player_id, &png, &mut redis).await?;
+
+    info!("Cached a {} byte avatar", png.len());
+    Ok(())
+}
@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// Used when neither `--config` nor `PUDDLE_FARM_CONFIG` is given.
+/// It's fine for this one to be missing.
+const DEFAULT_PATH: &str = "puddle-farm.toml";
+
+/// Key the game client encrypts api requests with.
+const GGST_AES_KEY: &str = "EEBC1F57487F51921C0465665F8AE6D1658BB26DE6F8A069A3520293A572078F";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings shared by every subcommand.
+/// Read from `puddle-farm.toml`, then the environment (and `.env`) overrides the file.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub worker_threads: usize,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub web: WebConfig,
+    pub pull: PullConfig,
+    pub ggst: GgstConfig,
+    pub rate_limit: RateLimitConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+    /// The pull process runs few queries at once, it doesn't need as many connections.
+    pub pull_pool_size: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    pub listen_addr: String,
+    /// How often to pick up characters registered by the pull process.
+    pub character_reload_seconds: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PullConfig {
+    pub metrics_addr: String,
+    pub replay_interval_seconds: u64,
+    pub processing_interval_seconds: u64,
+    pub webhook_interval_seconds: u64,
+    /// How often queued rating syncs are picked up.
+    pub rating_sync_interval_seconds: u64,
+    /// How often the scheduled refresh syncs leaderboard and recently active players.
+    pub refresh_interval_seconds: u64,
+    /// GGST requests the refresh may make per minute, shared with queued syncs which take two
+    /// (stats and avatar). 0 turns the refresh off and leaves queued syncs unlimited.
+    pub refresh_requests_per_minute: u32,
+    /// Players who played within this many days are refreshed along with the leaderboard.
+    pub refresh_active_days: i64,
+    /// How many players are kept in the global and per-character rankings.
+    pub rank_limit: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GgstConfig {
+    pub player_id: String,
+    pub steam_id: String,
+    pub steam_hex: String,
+    pub api_version: String,
+    pub aes_key: String,
+}
+
+/// Token buckets in Redis, per route group and per client.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Header the reverse proxy puts the client address in, the connection's address is used
+    /// without it. Only trust this behind a proxy that overwrites it.
+    pub client_ip_header: String,
+    /// Clients sending one of these in `X-API-Key` are limited per key instead of per address.
+    pub api_keys: Vec<String>,
+    /// Routes that are never limited, like the event stream and health probes.
+    pub exempt_routes: Vec<String>,
+    /// The `default` group covers every route not listed in another group.
+    pub groups: BTreeMap<String, RateLimitGroup>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitGroup {
+    /// Requests that can be made in a burst.
+    pub capacity: u32,
+    pub refill_per_minute: u32,
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            worker_threads: 5,
+            database: DatabaseConfig::default(),
+            redis: RedisConfig::default(),
+            web: WebConfig::default(),
+            pull: PullConfig::default(),
+            ggst: GgstConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 10,
+            pull_pool_size: 4,
+        }
+    }
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            listen_addr: "127.0.0.1:8001".to_string(),
+            character_reload_seconds: 600,
+        }
+    }
+}
+
+impl Default for PullConfig {
+    fn default() -> Self {
+        PullConfig {
+            metrics_addr: "127.0.0.1:8003".to_string(),
+            replay_interval_seconds: 60,
+            processing_interval_seconds: 50,
+            webhook_interval_seconds: 10,
+            rating_sync_interval_seconds: 5,
+            refresh_interval_seconds: 60,
+            refresh_requests_per_minute: 10,
+            refresh_active_days: 7,
+            rank_limit: 1000,
+        }
+    }
+}
+
+impl Default for GgstConfig {
+    fn default() -> Self {
+        GgstConfig {
+            player_id: String::new(),
+            steam_id: String::new(),
+            steam_hex: String::new(),
+            api_version: String::new(),
+            aes_key: GGST_AES_KEY.to_string(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let mut groups = BTreeMap::new();
+        groups.insert(
+            "default".to_string(),
+            RateLimitGroup {
+                capacity: 120,
+                refill_per_minute: 120,
+                routes: vec![],
+            },
+        );
+        groups.insert(
+            "heavy".to_string(),
+            RateLimitGroup {
+                capacity: 20,
+                refill_per_minute: 20,
+                routes: [
+                    "/api/player/:player_id/:char_id/history",
+                    "/api/player/search",
+                    "/api/ratings/:player_id/:char_id/:duration",
+                    "/api/matchups/:player_id/:char_id/:duration",
+                    "/api/avatar/:player_id",
+                ]
+                .iter()
+                .map(|r| r.to_string())
+                .collect(),
+            },
+        );
+
+        //Each sync costs the pull process GGST requests
+        groups.insert(
+            "sync".to_string(),
+            RateLimitGroup {
+                capacity: 3,
+                refill_per_minute: 3,
+                routes: vec!["/api/rating_sync/:player_id".to_string()],
+            },
+        );
+
+        RateLimitConfig {
+            enabled: true,
+            client_ip_header: String::new(),
+            api_keys: vec![],
+            exempt_routes: [
+                "/api/live",
+                "/api/health/live",
+                "/api/health/ready",
+                "/metrics",
+            ]
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+            groups,
+        }
+    }
+}
+
+/// The config loaded at startup, panics if `init` hasn't been called.
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("config::init must be called first")
+}
+
+/// Loads and validates the config, the error lists every problem found.
+/// `path` comes from `--config` and takes precedence over `PUDDLE_FARM_CONFIG`,
+/// the GGST account settings are only required when `ggst` is set.
+pub fn init(path: Option<&Path>, ggst: bool) -> Result<&'static Config, String> {
+    let config = load(path, ggst)?;
+
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+fn load(path: Option<&Path>, ggst: bool) -> Result<Config, String> {
+    let (path, required) = match (path, std::env::var("PUDDLE_FARM_CONFIG")) {
+        (Some(path), _) => (path.display().to_string(), true),
+        (None, Ok(path)) => (path, true),
+        (None, Err(_)) => (DEFAULT_PATH.to_string(), false),
+    };
+
+    let mut config = match std::fs::read_to_string(&path) {
+        Ok(contents) => match toml::from_str::<Config>(&contents) {
+            Ok(config) => config,
+            Err(e) => return Err(format!("{path}: {e}")),
+        },
+        Err(e) if required || e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Failed to read {path}: {e}"));
+        }
+        Err(_) => Config::default(),
+    };
+
+    apply_env(&mut config);
+
+    let errors = validate(&config, ggst);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    Ok(config)
+}
+
+/// The environment variable names predate the config file and are kept as they were.
+fn apply_env(config: &mut Config) {
+    for (name, field) in [
+        ("DATABASE_URL", &mut config.database.url),
+        ("REDIS_URL", &mut config.redis.url),
+        ("LISTEN_ADDR", &mut config.web.listen_addr),
+        ("PULL_METRICS_ADDR", &mut config.pull.metrics_addr),
+        ("PLAYER_ID", &mut config.ggst.player_id),
+        ("STEAM_ID", &mut config.ggst.steam_id),
+        ("STEAM_HEX", &mut config.ggst.steam_hex),
+        ("API_VERSION", &mut config.ggst.api_version),
+        ("GGST_AES_KEY", &mut config.ggst.aes_key),
+    ] {
+        if let Ok(value) = std::env::var(name) {
+            *field = value;
+        }
+    }
+}
+
+fn validate(config: &Config, ggst: bool) -> Vec<String> {
+    let mut errors = vec![];
+
+    let mut required = vec![
+        ("database.url", "DATABASE_URL", &config.database.url),
+        ("redis.url", "REDIS_URL", &config.redis.url),
+    ];
+    if ggst {
+        required.extend([
+            ("ggst.player_id", "PLAYER_ID", &config.ggst.player_id),
+            ("ggst.steam_id", "STEAM_ID", &config.ggst.steam_id),
+            ("ggst.steam_hex", "STEAM_HEX", &config.ggst.steam_hex),
+            ("ggst.api_version", "API_VERSION", &config.ggst.api_version),
+        ]);
+    }
+    for (name, env, value) in required {
+        if value.trim().is_empty() {
+            errors.push(format!("{name} must be set (or {env})"));
+        }
+    }
+
+    for (name, value) in [
+        ("web.listen_addr", &config.web.listen_addr),
+        ("pull.metrics_addr", &config.pull.metrics_addr),
+    ] {
+        if value.parse::<SocketAddr>().is_err() {
+            errors.push(format!("{name} is not a valid address: {value:?}"));
+        }
+    }
+
+    match hex::decode(&config.ggst.aes_key) {
+        Ok(key) if key.len() == 32 => {}
+        _ => errors.push("ggst.aes_key must be 64 hex characters".to_string()),
+    }
+
+    for (name, value) in [
+        ("worker_threads", config.worker_threads as u64),
+        ("database.pool_size", config.database.pool_size as u64),
+        (
+            "database.pull_pool_size",
+            config.database.pull_pool_size as u64,
+        ),
+        (
+            "web.character_reload_seconds",
+            config.web.character_reload_seconds,
+        ),
+        (
+            "pull.replay_interval_seconds",
+            config.pull.replay_interval_seconds,
+        ),
+        (
+            "pull.processing_interval_seconds",
+            config.pull.processing_interval_seconds,
+        ),
+        (
+            "pull.webhook_interval_seconds",
+            config.pull.webhook_interval_seconds,
+        ),
+        (
+            "pull.rating_sync_interval_seconds",
+            config.pull.rating_sync_interval_seconds,
+        ),
+        (
+            "pull.refresh_interval_seconds",
+            config.pull.refresh_interval_seconds,
+        ),
+    ] {
+        if value == 0 {
+            errors.push(format!("{name} must be greater than 0"));
+        }
+    }
+
+    if config.pull.rank_limit <= 0 {
+        errors.push("pull.rank_limit must be greater than 0".to_string());
+    }
+
+    if config.pull.refresh_active_days < 0 {
+        errors.push("pull.refresh_active_days can't be negative".to_string());
+    }
+
+    let mut grouped_routes = HashSet::new();
+    for (name, group) in &config.rate_limit.groups {
+        if group.capacity == 0 || group.refill_per_minute == 0 {
+            errors.push(format!(
+                "rate_limit.groups.{name} capacity and refill_per_minute must be greater than 0"
+            ));
+        }
+
+        for route in &group.routes {
+            if !grouped_routes.insert(route) {
+                errors.push(format!(
+                    "rate_limit route {route} is in more than one group"
+                ));
+            }
+        }
+    }
+
+    errors
+}
@@ -1,22 +1,86 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::models::{self, CharacterRank, Player, PlayerRating};
+use crate::models::{self, CharacterDailyStat, CharacterRank, Patch, Player, PlayerRating};
 use crate::models::GlobalRank;
 use crate::pull::Matchup;
-use crate::{schema, CHAR_NAMES};
-use diesel::sql_types::{BigInt, Integer, Timestamp};
+use crate::{characters, schema};
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, SmallInt, Text, Timestamp};
 use diesel::{prelude::*, update};
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
 
+/// Games a query covers: timestamps from `start` inclusive to `end` exclusive, recorded under
+/// `version` when it's set.
+#[derive(Clone, PartialEq)]
+pub struct GameScope {
+    pub start: chrono::NaiveDateTime,
+    pub end: chrono::NaiveDateTime,
+    pub version: Option<String>,
+}
+
+type GamesFilter = Box<dyn BoxableExpression<schema::games::table, diesel::pg::Pg, SqlType = Bool>>;
+
+impl GameScope {
+    /// Covers every game.
+    pub fn all() -> GameScope {
+        GameScope {
+            start: chrono::NaiveDate::from_ymd_opt(1970, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            end: chrono::NaiveDate::from_ymd_opt(3000, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            version: None,
+        }
+    }
+
+    /// Every game recorded under the patch's version.
+    pub fn patch(version: &str) -> GameScope {
+        GameScope {
+            version: Some(version.to_string()),
+            ..GameScope::all()
+        }
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == GameScope::all()
+    }
+
+    /// Same scope, starting no earlier than `start`.
+    pub fn since(&self, start: chrono::NaiveDateTime) -> GameScope {
+        GameScope {
+            start: self.start.max(start),
+            ..self.clone()
+        }
+    }
+
+    /// Matches the games in scope, for queries on the games table.
+    pub fn filter(&self) -> GamesFilter {
+        let in_range = schema::games::timestamp
+            .ge(self.start)
+            .and(schema::games::timestamp.lt(self.end));
+
+        match &self.version {
+            Some(version) => {
+                Box::new(in_range.and(schema::games::version.eq(version.clone()).assume_not_null()))
+            }
+            None => Box::new(in_range),
+        }
+    }
+}
+
+/// Adds the rating if the player doesn't have one for this character yet.
 pub async fn set_player_rating(
     id: i64,
     char_id: i16,
     value: i64,
-    db: &mut crate::Connection<'_>,
+    db: &mut AsyncPgConnection,
 ) -> Result<(), String> {
-    match diesel::update(schema::player_ratings::table)
-        .filter(schema::player_ratings::id.eq(id))
-        .filter(schema::player_ratings::char_id.eq(char_id))
+    match diesel::insert_into(schema::player_ratings::table)
+        .values(&PlayerRating { id, char_id, value })
+        .on_conflict((schema::player_ratings::id, schema::player_ratings::char_id))
+        .do_update()
         .set(schema::player_ratings::value.eq(value))
         .execute(db)
         .await
@@ -26,6 +90,149 @@ pub async fn set_player_rating(
     }
 }
 
+pub async fn get_player(id: i64, db: &mut AsyncPgConnection) -> Result<Option<Player>, String> {
+    match schema::players::table
+        .filter(schema::players::id.eq(id))
+        .select(Player::as_select())
+        .first(db)
+        .await
+        .optional()
+    {
+        Ok(player) => Ok(player),
+        Err(e) => Err(format!("Error loading player: {}", e)),
+    }
+}
+
+/// Each character's current rating.
+pub async fn get_player_ratings(
+    id: i64,
+    db: &mut AsyncPgConnection,
+) -> Result<HashMap<i16, i64>, String> {
+    match schema::player_ratings::table
+        .filter(schema::player_ratings::id.eq(id))
+        .select((schema::player_ratings::char_id, schema::player_ratings::value))
+        .load::<(i16, i64)>(db)
+        .await
+    {
+        Ok(ratings) => Ok(ratings.into_iter().collect()),
+        Err(e) => Err(format!("Error loading player ratings: {}", e)),
+    }
+}
+
+/// Replaces the stored official totals for each character in `stats`.
+pub async fn set_official_stats(
+    stats: &[models::PlayerOfficialStat],
+    db: &mut AsyncPgConnection,
+) -> Result<(), String> {
+    use diesel::upsert::excluded;
+    use schema::player_official_stats::dsl::*;
+
+    if stats.is_empty() {
+        return Ok(());
+    }
+
+    match diesel::insert_into(player_official_stats)
+        .values(stats)
+        .on_conflict((id, char_id))
+        .do_update()
+        .set((
+            level.eq(excluded(level)),
+            ranked_games.eq(excluded(ranked_games)),
+            ranked_wins.eq(excluded(ranked_wins)),
+            updated_at.eq(excluded(updated_at)),
+        ))
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error setting official stats: {}", e)),
+    }
+}
+
+/// The name and platform the player had in their newest replay.
+pub async fn get_latest_profile(
+    id: i64,
+    db: &mut AsyncPgConnection,
+) -> Result<Option<(String, i16)>, String> {
+    use schema::games;
+
+    let side_a = match games::table
+        .filter(games::id_a.eq(id))
+        .order(games::timestamp.desc())
+        .select((games::timestamp, games::name_a, games::platform_a))
+        .first::<(chrono::NaiveDateTime, String, i16)>(db)
+        .await
+        .optional()
+    {
+        Ok(side) => side,
+        Err(e) => return Err(format!("Error loading latest game: {}", e)),
+    };
+    let side_b = match games::table
+        .filter(games::id_b.eq(id))
+        .order(games::timestamp.desc())
+        .select((games::timestamp, games::name_b, games::platform_b))
+        .first::<(chrono::NaiveDateTime, String, i16)>(db)
+        .await
+        .optional()
+    {
+        Ok(side) => side,
+        Err(e) => return Err(format!("Error loading latest game: {}", e)),
+    };
+
+    Ok(side_a
+        .into_iter()
+        .chain(side_b)
+        .max_by_key(|(timestamp, _, _)| *timestamp)
+        .map(|(_, name, platform)| (name, platform)))
+}
+
+/// Sets a known player's name and platform and keeps the name as an alias.
+pub async fn update_player_profile(
+    id: i64,
+    name: &str,
+    platform: i16,
+    db: &mut AsyncPgConnection,
+) -> Result<(), String> {
+    if let Err(e) = update(schema::players::table.filter(schema::players::id.eq(id)))
+        .set((
+            schema::players::name.eq(name),
+            schema::players::platform.eq(platform),
+        ))
+        .execute(db)
+        .await
+    {
+        return Err(format!("Error updating player: {}", e));
+    }
+
+    match diesel::insert_into(schema::player_names::table)
+        .values(&models::PlayerName {
+            id,
+            name: name.to_string(),
+        })
+        .on_conflict_do_nothing()
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error adding player alias: {}", e)),
+    }
+}
+
+async fn get_official_stats(
+    player_id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashMap<i16, models::PlayerOfficialStat>, String> {
+    match schema::player_official_stats::table
+        .filter(schema::player_official_stats::id.eq(player_id))
+        .select(models::PlayerOfficialStat::as_select())
+        .load(db)
+        .await
+    {
+        Ok(stats) => Ok(stats.into_iter().map(|s| (s.char_id, s)).collect()),
+        Err(e) => Err(format!("Error loading official stats: {}", e)),
+    }
+}
+
 async fn get_player_char_and_rating(
     id: i64,
     db: &mut crate::Connection<'_>,
@@ -62,19 +269,19 @@ async fn get_global_rank(id: i64, db: &mut crate::Connection<'_>) -> Result<i32,
 async fn get_match_count(
     id: i64,
     char_id: i16,
+    range: &GameScope,
     db: &mut crate::Connection<'_>,
 ) -> Result<i64, String> {
     match schema::games::table
         .filter(
-            schema::games::id_a
+            (schema::games::id_a
                 .eq(id)
-                .and(schema::games::char_a.eq(char_id)),
-        )
-        .or_filter(
-            schema::games::id_b
+                .and(schema::games::char_a.eq(char_id)))
+            .or(schema::games::id_b
                 .eq(id)
-                .and(schema::games::char_b.eq(char_id)),
+                .and(schema::games::char_b.eq(char_id))),
         )
+        .filter(range.filter())
         .count()
         .get_result::<i64>(db)
         .await
@@ -105,6 +312,7 @@ async fn get_top_char(
 async fn get_top_defeated(
     id: i64,
     char_id: i16,
+    range: &GameScope,
     db: &mut crate::Connection<'_>,
 ) -> Result<
     Vec<(
@@ -129,6 +337,7 @@ async fn get_top_defeated(
                 .eq(id)
                 .and(schema::games::char_a.eq(char_id))
                 .and(schema::games::winner.eq(1))
+                .and(range.filter())
         )
         .order(schema::games::value_b.desc())
         .limit(1)
@@ -148,6 +357,7 @@ async fn get_top_defeated(
                         .eq(id)
                         .and(schema::games::char_b.eq(char_id))
                         .and(schema::games::winner.eq(2))
+                        .and(range.filter())
                 ),
         )
         //TODO use this instead when positional_order_by + limit is released
@@ -170,6 +380,7 @@ async fn get_top_defeated(
 async fn get_top_rating(
     id: i64,
     char_id: i16,
+    range: &GameScope,
     db: &mut crate::Connection<'_>,
 ) -> Result<Vec<(chrono::NaiveDateTime, i64)>, String> {
     match schema::games::table
@@ -181,6 +392,7 @@ async fn get_top_rating(
             schema::games::id_a
                 .eq(id)
                 .and(schema::games::char_a.eq(char_id))
+                .and(range.filter())
         )
         .order(schema::games::value_a.desc())
         .limit(1)
@@ -194,6 +406,7 @@ async fn get_top_rating(
                     schema::games::id_b
                         .eq(id)
                         .and(schema::games::char_b.eq(char_id))
+                        .and(range.filter())
                 )
                 .order(schema::games::value_b.desc())
                 .limit(1),
@@ -209,6 +422,21 @@ async fn get_top_rating(
     }
 }
 
+async fn get_player_char_stats(
+    id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::PlayerCharStat>, String> {
+    match schema::player_char_stats::table
+        .select(models::PlayerCharStat::as_select())
+        .filter(schema::player_char_stats::id.eq(id))
+        .load(db)
+        .await
+    {
+        Ok(stats) => Ok(stats),
+        Err(_) => Err("Player char stats not found".to_string()),
+    }
+}
+
 async fn get_tags(
     id: i64,
     db: &mut crate::Connection<'_>,
@@ -255,6 +483,7 @@ pub async fn get_tags_from_player_list(
 
 pub async fn get_player_response_data(
     id: i64,
+    range: GameScope,
     db: &mut crate::Connection<'_>,
 ) -> Result<
     (
@@ -263,8 +492,10 @@ pub async fn get_player_response_data(
         HashMap<i16, i32>,
         HashMap<i16, crate::handlers::player::TopDefeated>,
         HashMap<i16, crate::handlers::player::TopRating>,
+        HashMap<i16, crate::handlers::player::Percentile>,
         i32,
         Vec<(String, String)>,
+        HashMap<i16, models::PlayerOfficialStat>,
     ),
     String,
 > {
@@ -285,26 +516,78 @@ pub async fn get_player_response_data(
         Err(_) => 0,
     };
 
-    for (player, rating) in player_char.iter() {
-        let match_count = match get_match_count(player.id, rating.char_id, db).await {
-            Ok(count) => count,
+    //All time stats are kept up to date by the pull process, patches still go through the games table
+    let char_stats = if range.is_all() {
+        match get_player_char_stats(id, db).await {
+            Ok(stats) => Some(stats),
             Err(e) => return Err(e),
-        };
-        match_counts.insert(rating.char_id, match_count as i32);
+        }
+    } else {
+        None
+    };
 
+    for (player, rating) in player_char.iter() {
         let top_char = match get_top_char(id, rating.char_id, db).await {
             Ok(rank) => rank,
             Err(_) => 0,
         };
         top_chars.insert(rating.char_id, top_char);
 
+        if let Some(char_stats) = &char_stats {
+            //Ratings can come from a rating sync without any stored games
+            let stat = match char_stats.iter().find(|s| s.char_id == rating.char_id) {
+                Some(stat) => stat,
+                None => {
+                    match_counts.insert(rating.char_id, 0);
+                    continue;
+                }
+            };
+
+            match_counts.insert(rating.char_id, stat.games as i32);
+
+            top_rating.insert(
+                rating.char_id,
+                crate::handlers::player::TopRating {
+                    timestamp: stat.peak_timestamp.to_string(),
+                    value: stat.peak_rating,
+                },
+            );
+
+            if let (Some(timestamp), Some(id), Some(name), Some(char_id), Some(value)) = (
+                stat.top_defeated_timestamp,
+                stat.top_defeated_id,
+                &stat.top_defeated_name,
+                stat.top_defeated_char,
+                stat.top_defeated_value,
+            ) {
+                top_defeated.insert(
+                    rating.char_id,
+                    crate::handlers::player::TopDefeated {
+                        timestamp: timestamp.to_string(),
+                        id,
+                        name: name.clone(),
+                        char_short: characters::get(char_id).short,
+                        value,
+                    },
+                );
+            }
+
+            continue;
+        }
+
+        let match_count = match get_match_count(player.id, rating.char_id, &range, db).await {
+            Ok(count) => count,
+            Err(e) => return Err(e),
+        };
+        match_counts.insert(rating.char_id, match_count as i32);
+
         let top_defeated_res: Vec<(
             chrono::NaiveDateTime,
             i64,
             String,
             i16,
             i64,
-        )> = match get_top_defeated(id, rating.char_id, db).await {
+        )> = match get_top_defeated(id, rating.char_id, &range, db).await {
             Ok(res) => res,
             Err(e) => return Err(e),
         };
@@ -324,16 +607,14 @@ pub async fn get_player_response_data(
                     timestamp: top_defeated_res[highest_index].0.to_string(),
                     id: top_defeated_res[highest_index].1,
                     name: top_defeated_res[highest_index].2.clone(),
-                    char_short: CHAR_NAMES[top_defeated_res[highest_index].3 as usize]
-                        .0
-                        .to_string(),
+                    char_short: characters::get(top_defeated_res[highest_index].3).short,
                     value: top_defeated_res[highest_index].4,
                 },
             );
         }
 
         let top_rating_res: Vec<(chrono::NaiveDateTime, i64)> =
-            match get_top_rating(id, rating.char_id, db).await {
+            match get_top_rating(id, rating.char_id, &range, db).await {
                 Ok(res) => res,
                 Err(e) => return Err(e),
             };
@@ -357,19 +638,41 @@ pub async fn get_player_response_data(
         }
     }
 
+    let percentiles = match get_percentiles(id, None, db).await {
+        Ok(res) => res
+            .iter()
+            .map(|p| {
+                (
+                    p.char_id,
+                    crate::handlers::player::Percentile {
+                        tier: p.tier.clone(),
+                        percentile: crate::handlers::percentile::calc_percentile(
+                            p.value, p.below, p.total,
+                        ),
+                    },
+                )
+            })
+            .collect(),
+        Err(e) => return Err(e),
+    };
+
     let tags = match get_tags(id, db).await {
         Ok(tags) => tags,
         Err(e) => return Err(e),
     };
 
+    let official_stats = get_official_stats(id, db).await?;
+
     Ok((
         player_char,
         match_counts,
         top_chars,
         top_defeated,
         top_rating,
+        percentiles,
         top_global,
         tags,
+        official_stats,
     ))
 }
 
@@ -378,6 +681,7 @@ pub async fn get_games(
     char_id: i16,
     count: i64,
     offset: i64,
+    range: GameScope,
     db: &mut crate::Connection<'_>,
 ) -> Result<Vec<models::Game>, String> {
     match schema::games::table
@@ -389,6 +693,7 @@ pub async fn get_games(
                 .eq(id)
                 .and(schema::games::char_b.eq(char_id))),
         )
+        .filter(range.filter())
         .select(models::Game::as_select())
         .order(
             crate::pull::coalesce(schema::games::real_timestamp, schema::games::timestamp).desc(),
@@ -460,33 +765,101 @@ pub async fn get_top_for_char(
     }
 }
 
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct SearchRow {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    matched_name: String,
+}
+/// Players whose current or old names match, each once with their best matching name.
+/// Contains searches also match similar names, ranked by trigram similarity, then by rating.
 pub async fn find_player(
-    search_params: crate::handlers::search::SearchParams,
+    search_string: &str,
+    mode: crate::handlers::search::SearchMode,
+    count: i64,
+    offset: i64,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<(Player, PlayerRating)>, String> {
-    let count = 100;
-    let offset = 0;
+) -> Result<Vec<crate::handlers::search::SearchMatch>, String> {
+    use crate::handlers::search::{like_pattern, search_id, SearchMode};
 
-    let exact_like = if search_params.exact.unwrap_or(false) {
-        format!("{}", search_params.search_string)
-    } else {
-        format!("%{}%", search_params.search_string)
+    //Each mode's filter is one its index can serve, exact from lower(name) and the rest from
+    //the trigram indexes
+    let (name_filter, pattern) = match mode {
+        SearchMode::Exact => ("lower(name) = lower($2)", search_string.to_string()),
+        SearchMode::Prefix => ("name ILIKE $2", like_pattern(search_string, mode)),
+        SearchMode::Contains => (
+            "(name % $1 OR name ILIKE $2)",
+            like_pattern(search_string, mode),
+        ),
     };
 
-    match schema::players::table
-        .inner_join(
-            schema::player_ratings::table.on(schema::player_ratings::id.eq(schema::players::id)),
+    let results = diesel::sql_query(format!(
+        "
+        WITH matches AS (
+            SELECT id, name, similarity(name, $1) AS score
+            FROM players
+            WHERE {name_filter}
+            UNION ALL
+            SELECT id, name, similarity(name, $1) AS score
+            FROM player_names
+            WHERE {name_filter}
+            UNION ALL
+            SELECT id, name, 2.0::real AS score
+            FROM players
+            WHERE id = $3
+        ),
+        best AS (
+            SELECT DISTINCT ON (id) id, name AS matched_name, score
+            FROM matches
+            ORDER BY id, score DESC
         )
-        .select((Player::as_select(), PlayerRating::as_select()))
-        .filter(schema::players::name.ilike(exact_like))
-        .order(schema::player_ratings::value.desc())
-        .limit(count)
-        .offset(offset)
+        SELECT b.id, p.name, b.matched_name
+        FROM best b
+        JOIN players p ON p.id = b.id
+        JOIN LATERAL (
+            SELECT MAX(value) AS best_rating FROM player_ratings r WHERE r.id = b.id
+        ) r ON r.best_rating IS NOT NULL
+        ORDER BY b.score DESC, r.best_rating DESC, b.id
+        LIMIT $4 OFFSET $5
+        "
+    ))
+    .bind::<Text, _>(search_string)
+    .bind::<Text, _>(pattern)
+    .bind::<diesel::sql_types::Nullable<BigInt>, _>(search_id(search_string))
+    .bind::<BigInt, _>(count)
+    .bind::<BigInt, _>(offset)
+    .get_results::<SearchRow>(db)
+    .await;
+
+    match results {
+        Ok(results) => Ok(results
+            .into_iter()
+            .map(|r| crate::handlers::search::SearchMatch {
+                id: r.id,
+                name: r.name,
+                matched_name: r.matched_name,
+            })
+            .collect()),
+        Err(_) => Err("Player not found".to_string()),
+    }
+}
+
+pub async fn get_ratings_for_players(
+    ids: &[i64],
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<PlayerRating>, String> {
+    match schema::player_ratings::table
+        .select(PlayerRating::as_select())
+        .filter(schema::player_ratings::id.eq_any(ids))
         .load(db)
         .await
     {
-        Ok(player) => Ok(player),
-        Err(_) => Err("Player not found".to_string()),
+        Ok(ratings) => Ok(ratings),
+        Err(_) => Err("Ratings not found".to_string()),
     }
 }
 
@@ -675,10 +1048,60 @@ pub async fn get_ratings(
     }
 }
 
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PercentileResult {
+    #[diesel(sql_type = SmallInt)]
+    pub char_id: i16,
+    #[diesel(sql_type = BigInt)]
+    pub value: i64,
+    #[diesel(sql_type = Text)]
+    pub tier: String,
+    #[diesel(sql_type = BigInt)]
+    pub below: i64,
+    #[diesel(sql_type = BigInt)]
+    pub total: i64,
+}
+pub async fn get_percentiles(
+    id: i64,
+    char_id: Option<i16>,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<PercentileResult>, String> {
+    // Counts are taken live from player_ratings, the same population as the distribution.
+    // Placement players (value < 1) are excluded.
+    let results = diesel::sql_query(
+        "
+        SELECT
+            r.char_id,
+            r.value,
+            COALESCE(t.name, 'Unknown') AS tier,
+            (SELECT count(*) FROM player_ratings o
+             WHERE o.char_id = r.char_id AND o.value >= 1 AND o.value < r.value) AS below,
+            (SELECT count(*) FROM player_ratings o
+             WHERE o.char_id = r.char_id AND o.value >= 1) AS total
+        FROM player_ratings r
+        LEFT JOIN rank_tiers t ON r.value >= t.lower_bound AND r.value < t.upper_bound
+        WHERE r.id = $1
+        AND ($2 = -1 OR r.char_id = $2);
+        ",
+    );
+
+    match results
+        .bind::<BigInt, _>(id)
+        .bind::<Integer, _>(char_id.map(i32::from).unwrap_or(-1))
+        .get_results::<PercentileResult>(db)
+        .await
+    {
+        Ok(results) => Ok(results),
+        Err(_) => Err("Percentile not found".to_string()),
+    }
+}
+
 pub async fn get_matchups(
     id: i64,
     char_id: i16,
     duration: i32,
+    range: GameScope,
     db: &mut crate::Connection<'_>,
 ) -> Result<Vec<Matchup>, String> {
     let results = diesel::sql_query(
@@ -700,6 +1123,9 @@ pub async fn get_matchups(
         WHERE char_a = $1
         AND id_a = $2
         AND timestamp > now() - ($3 || ' week')::interval
+        AND timestamp >= $4
+        AND timestamp < $5
+        AND ($6::text IS NULL OR version = $6)
         UNION ALL
         SELECT 
             char_a as opponent_char, 
@@ -709,6 +1135,9 @@ pub async fn get_matchups(
         WHERE char_b = $1
         AND id_b = $2
         AND timestamp > now() - ($3 || ' week')::interval
+        AND timestamp >= $4
+        AND timestamp < $5
+        AND ($6::text IS NULL OR version = $6)
     ) as combined_results
     GROUP BY opponent_char
     ORDER BY opponent_char;
@@ -718,6 +1147,9 @@ pub async fn get_matchups(
         .bind::<Integer, _>(i32::try_from(char_id).unwrap())
         .bind::<BigInt, _>(i64::try_from(id).unwrap())
         .bind::<Integer, _>(i32::try_from(duration).unwrap())
+        .bind::<Timestamp, _>(range.start)
+        .bind::<Timestamp, _>(range.end)
+        .bind::<Nullable<Text>, _>(range.version)
         .get_results::<crate::pull::Matchup>(db)
         .await
     {
@@ -726,6 +1158,47 @@ pub async fn get_matchups(
     }
 }
 
+pub async fn get_character_daily_stats(
+    days: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<CharacterDailyStat>, String> {
+    let since = chrono::Utc::now().date_naive() - chrono::Duration::days(days);
+
+    match schema::character_daily_stats::table
+        .select(CharacterDailyStat::as_select())
+        .filter(schema::character_daily_stats::day.gt(since))
+        .order((
+            schema::character_daily_stats::day.asc(),
+            schema::character_daily_stats::char_id.asc(),
+        ))
+        .load(db)
+        .await
+    {
+        Ok(stats) => Ok(stats),
+        Err(_) => Err("Trends not found".to_string()),
+    }
+}
+
+pub async fn get_patches(db: &mut crate::Connection<'_>) -> Result<Vec<Patch>, String> {
+    match schema::patches::table
+        .select(Patch::as_select())
+        .order(schema::patches::start_date.asc())
+        .load(db)
+        .await
+    {
+        Ok(patches) => Ok(patches),
+        Err(_) => Err("Patches not found".to_string()),
+    }
+}
+
+/// When the patch ended, the next patch's start, or None for the current or an unknown patch.
+/// `patches` must be ordered by start date.
+pub fn get_patch_end(patches: &[Patch], version: &str) -> Option<chrono::NaiveDateTime> {
+    let index = patches.iter().position(|p| p.version == version)?;
+
+    patches.get(index + 1).map(|next| next.start_date)
+}
+
 pub async fn get_supporters(db: &mut crate::Connection<'_>) -> Result<Vec<(i64, String)>, String> {
     match schema::tags::table
         .inner_join(schema::players::table.on(schema::tags::player_id.eq(schema::players::id)))
@@ -739,6 +1212,13 @@ pub async fn get_supporters(db: &mut crate::Connection<'_>) -> Result<Vec<(i64,
     }
 }
 
+pub async fn ping(db: &mut crate::Connection<'_>) -> Result<(), String> {
+    match diesel::sql_query("SELECT 1").execute(db).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 pub async fn player_exists(db: &mut crate::Connection<'_>, player_id: i64) -> Result<bool, String> {
     let exists = match schema::players::table
         .filter(schema::players::id.eq(player_id))
@@ -752,3 +1232,273 @@ pub async fn player_exists(db: &mut crate::Connection<'_>, player_id: i64) -> Re
 
     exists
 }
+
+pub async fn get_webhooks(
+    owner_id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::Webhook>, String> {
+    match schema::webhooks::table
+        .select(models::Webhook::as_select())
+        .filter(schema::webhooks::owner_id.eq(owner_id))
+        .order(schema::webhooks::id.asc())
+        .load(db)
+        .await
+    {
+        Ok(webhooks) => Ok(webhooks),
+        Err(_) => Err("Webhooks not found".to_string()),
+    }
+}
+
+pub async fn get_webhooks_for_targets(
+    target_ids: Vec<i64>,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::Webhook>, String> {
+    match schema::webhooks::table
+        .select(models::Webhook::as_select())
+        .filter(schema::webhooks::target_id.eq_any(target_ids))
+        .load(db)
+        .await
+    {
+        Ok(webhooks) => Ok(webhooks),
+        Err(_) => Err("Webhooks not found".to_string()),
+    }
+}
+
+/// Which of `webhook_ids` haven't been deleted.
+pub async fn get_existing_webhook_ids(
+    webhook_ids: Vec<i32>,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashSet<i32>, String> {
+    match schema::webhooks::table
+        .select(schema::webhooks::id)
+        .filter(schema::webhooks::id.eq_any(webhook_ids))
+        .load::<i32>(db)
+        .await
+    {
+        Ok(ids) => Ok(ids.into_iter().collect()),
+        Err(_) => Err("Webhooks not found".to_string()),
+    }
+}
+
+pub async fn create_webhook(
+    owner_id: i64,
+    request: &crate::handlers::webhooks::WebhookRequest,
+    db: &mut crate::Connection<'_>,
+) -> Result<models::Webhook, String> {
+    match diesel::insert_into(schema::webhooks::table)
+        .values((
+            schema::webhooks::owner_id.eq(owner_id),
+            schema::webhooks::target_id.eq(request.target_id),
+            schema::webhooks::url.eq(&request.url),
+            schema::webhooks::on_game.eq(request.on_game.unwrap_or(false)),
+            schema::webhooks::on_rating_high.eq(request.on_rating_high.unwrap_or(false)),
+            schema::webhooks::on_top_100.eq(request.on_top_100.unwrap_or(false)),
+        ))
+        .returning(models::Webhook::as_returning())
+        .get_result(db)
+        .await
+    {
+        Ok(webhook) => Ok(webhook),
+        Err(_) => Err("Failed to create webhook".to_string()),
+    }
+}
+
+pub async fn delete_webhook(
+    owner_id: i64,
+    webhook_id: i32,
+    db: &mut crate::Connection<'_>,
+) -> Result<(), String> {
+    match diesel::delete(
+        schema::webhooks::table
+            .filter(schema::webhooks::id.eq(webhook_id))
+            .filter(schema::webhooks::owner_id.eq(owner_id)),
+    )
+    .execute(db)
+    .await
+    {
+        Ok(0) => Err("Webhook not found".to_string()),
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to delete webhook".to_string()),
+    }
+}
+
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct RatingHigh {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+    #[diesel(sql_type = SmallInt)]
+    char_id: i16,
+    #[diesel(sql_type = BigInt)]
+    value: i64,
+}
+/// Highest rating each player reached on each character in games before `before`.
+pub async fn get_rating_highs(
+    ids: Vec<i64>,
+    before: chrono::NaiveDateTime,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashMap<(i64, i16), i64>, String> {
+    let results = diesel::sql_query(
+        "
+        SELECT id, char_id, MAX(value) as value
+        FROM (
+            SELECT id_a as id, char_a as char_id, value_a as value
+            FROM games
+            WHERE id_a = ANY($1) AND timestamp < $2
+            UNION ALL
+            SELECT id_b as id, char_b as char_id, value_b as value
+            FROM games
+            WHERE id_b = ANY($1) AND timestamp < $2
+        ) as combined_results
+        GROUP BY id, char_id
+        ",
+    )
+    .bind::<diesel::sql_types::Array<BigInt>, _>(ids)
+    .bind::<Timestamp, _>(before)
+    .get_results::<RatingHigh>(db)
+    .await;
+
+    match results {
+        Ok(results) => Ok(results
+            .into_iter()
+            .map(|r| ((r.id, r.char_id), r.value))
+            .collect()),
+        Err(_) => Err("Rating highs not found".to_string()),
+    }
+}
+
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TrackedRank {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+    #[diesel(sql_type = Integer)]
+    pub rank: i32,
+    #[diesel(sql_type = SmallInt)]
+    pub char_id: i16,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = BigInt)]
+    pub value: i64,
+}
+/// Global ranks of the players someone watches for top 100 entries.
+pub async fn get_tracked_global_ranks(
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<TrackedRank>, String> {
+    let results = diesel::sql_query(
+        "
+        SELECT g.id, g.rank, g.char_id, p.name, r.value
+        FROM global_ranks g
+        JOIN players p ON p.id = g.id
+        JOIN player_ratings r ON r.id = g.id AND r.char_id = g.char_id
+        WHERE g.id IN (SELECT target_id FROM webhooks WHERE on_top_100)
+        ",
+    )
+    .get_results::<TrackedRank>(db)
+    .await;
+
+    match results {
+        Ok(results) => Ok(results),
+        Err(_) => Err("Global ranks not found".to_string()),
+    }
+}
+
+pub async fn get_rank_tiers(db: &mut AsyncPgConnection) -> Result<Vec<models::RankTier>, String> {
+    match schema::rank_tiers::table
+        .select(models::RankTier::as_select())
+        .order(schema::rank_tiers::lower_bound.asc())
+        .load(db)
+        .await
+    {
+        Ok(tiers) => Ok(tiers),
+        Err(_) => Err("Rank tiers not found".to_string()),
+    }
+}
+
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct RatingStateResult {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+    #[diesel(sql_type = SmallInt)]
+    char_id: i16,
+    #[diesel(sql_type = BigInt)]
+    value: i64,
+    #[diesel(sql_type = BigInt)]
+    best: i64,
+}
+/// Latest and highest rating of each player on each character in the stored games.
+pub async fn get_rating_states(
+    ids: Vec<i64>,
+    db: &mut AsyncPgConnection,
+) -> Result<HashMap<(i64, i16), crate::handlers::events::RatingState>, String> {
+    let results = diesel::sql_query(
+        "
+        SELECT DISTINCT ON (id, char_id)
+            id, char_id, value, MAX(value) OVER (PARTITION BY id, char_id) as best
+        FROM (
+            SELECT timestamp, id_a as id, char_a as char_id, value_a as value
+            FROM games
+            WHERE id_a = ANY($1)
+            UNION ALL
+            SELECT timestamp, id_b as id, char_b as char_id, value_b as value
+            FROM games
+            WHERE id_b = ANY($1)
+        ) as combined_results
+        ORDER BY id, char_id, timestamp DESC
+        ",
+    )
+    .bind::<diesel::sql_types::Array<BigInt>, _>(ids)
+    .get_results::<RatingStateResult>(db)
+    .await;
+
+    match results {
+        Ok(results) => Ok(results
+            .into_iter()
+            .map(|r| {
+                (
+                    (r.id, r.char_id),
+                    crate::handlers::events::RatingState {
+                        value: r.value,
+                        best: r.best,
+                    },
+                )
+            })
+            .collect()),
+        Err(_) => Err("Rating states not found".to_string()),
+    }
+}
+
+pub async fn insert_events(
+    events: &[models::NewEvent],
+    db: &mut AsyncPgConnection,
+) -> Result<(), String> {
+    match diesel::insert_into(schema::events::table)
+        .values(events)
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to insert events: {e}")),
+    }
+}
+
+pub async fn get_events(
+    player_id: i64,
+    count: i64,
+    offset: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::Event>, String> {
+    match schema::events::table
+        .select(models::Event::as_select())
+        .filter(schema::events::player_id.eq(player_id))
+        .order((schema::events::timestamp.desc(), schema::events::id.desc()))
+        .limit(count)
+        .offset(offset)
+        .load(db)
+        .await
+    {
+        Ok(events) => Ok(events),
+        Err(_) => Err("Events not found".to_string()),
+    }
+}
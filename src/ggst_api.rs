@@ -28,12 +28,18 @@ pub async fn get_player_stats(player_id: String) -> Result<String, String> {
         .header("x-client-version", "1")
         .form(&[("data", request_data)]);
 
-    let response = form.send().await.unwrap();
-    let response_bytes = response.bytes().await.unwrap();
+    let response_bytes = match send(form).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            crate::monitoring::ggst_api_error("statistics/get");
+            return Err(format!("Couldn't get player stats: {e}"));
+        }
+    };
 
     if let Ok(r) = decrypt_response::<responses::PlayerStats>(&response_bytes) {
         Ok(r.body.json)
     } else {
+        crate::monitoring::ggst_api_error("statistics/get");
         return Err("Couldn't get player stats".to_owned());
     }
 }
@@ -52,16 +58,27 @@ pub async fn get_player_avatar(player_id: String) -> Result<String, String> {
       .header("x-client-version", "1")
       .form(&[("data", request_data)]);
 
-  let response = form.send().await.unwrap();
-  let response_bytes = response.bytes().await.unwrap();
+  let response_bytes = match send(form).await {
+      Ok(bytes) => bytes,
+      Err(e) => {
+          crate::monitoring::ggst_api_error("tus/read");
+          return Err(format!("Couldn't get player avatar: {e}"));
+      }
+  };
 
   if let Ok(r) = decrypt_response::<responses::PlayerAvatar>(&response_bytes) {
       Ok(r.body.png)
   } else {
+      crate::monitoring::ggst_api_error("tus/read");
       return Err("Couldn't get player avatar".to_owned());
   }
 }
 
+/// The response body, failing if the request or reading the body fails.
+async fn send(form: reqwest::RequestBuilder) -> Result<Vec<u8>, reqwest::Error> {
+    Ok(form.send().await?.bytes().await?.to_vec())
+}
+
 pub async fn get_token() -> Result<String, String> {
     {
         let token = TOKEN.lock().await;
@@ -72,6 +89,7 @@ pub async fn get_token() -> Result<String, String> {
     }
 
     warn!("Grabbing steam token");
+    metrics::counter!("ggst_token_refreshes_total").increment(1);
     let request_data = requests::generate_login_request().await;
     let request_data = encrypt_data(&request_data);
 
@@ -114,6 +132,7 @@ pub async fn get_token() -> Result<String, String> {
 
         Ok(r.header.token)
     } else {
+        crate::monitoring::ggst_api_error("user/login");
         return Err("Couldn't get strive token".to_owned());
     }
 }
@@ -138,6 +157,7 @@ pub async fn get_replays() -> Result<Vec<responses::Replay>, String> {
             Ok(resp) => resp,
             Err(err) => {
                 error!("get_replay send() error: {}", err);
+                crate::monitoring::ggst_api_error("catalog/get_replay");
                 panic!("get_replay send() error: {}", err); //force crash to restart server
             }
         };
@@ -146,6 +166,7 @@ pub async fn get_replays() -> Result<Vec<responses::Replay>, String> {
             Ok(resp_bytes) => resp_bytes,
             Err(err) => {
                 error!("get_replay bytes() error: {}", err);
+                crate::monitoring::ggst_api_error("catalog/get_replay");
                 panic!("get_replay bytes() error: {}", err); //force crash to restart server
             }
         };
@@ -154,6 +175,7 @@ pub async fn get_replays() -> Result<Vec<responses::Replay>, String> {
             Ok(r) => replays.extend_from_slice(&r.body.replays),
             Err(err) => {
                 error!("get_replay decrypt_response() error: {}", err);
+                crate::monitoring::ggst_api_error("catalog/get_replay");
                 panic!("get_replay decrypt_response() error: {}", err); //force crash to restart server
             }
         };
@@ -163,8 +185,7 @@ pub async fn get_replays() -> Result<Vec<responses::Replay>, String> {
 }
 
 fn encrypt_data<T: Serialize>(data: &T) -> String {
-    let key =
-        hex::decode("EEBC1F57487F51921C0465665F8AE6D1658BB26DE6F8A069A3520293A572078F").unwrap();
+    let key = hex::decode(&crate::config::get().ggst.aes_key).unwrap();
 
     let bytes = rmp_serde::to_vec(data).unwrap();
     //let mut nonce = [0u8; 12];
@@ -187,8 +208,7 @@ fn encrypt_data<T: Serialize>(data: &T) -> String {
 fn decrypt_response<T: for<'a> Deserialize<'a>>(
     bytes: &[u8],
 ) -> Result<Response<T>, Box<dyn Error>> {
-    let key =
-        hex::decode("EEBC1F57487F51921C0465665F8AE6D1658BB26DE6F8A069A3520293A572078F").unwrap();
+    let key = hex::decode(&crate::config::get().ggst.aes_key).unwrap();
     let aes_gcm = Aes256Gcm::new_from_slice(&key).unwrap();
 
     let mut nonce = [0; 12];
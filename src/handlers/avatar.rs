@@ -1,36 +1,222 @@
-use axum::body::Bytes;
+use std::io::Cursor;
 
-fn process_channel(value: u8) -> u8 {
-    if value != 0 && value != 255 {
-        let n = f64::sqrt(value as f64 / 255.0);
-        (n * 255.0) as u8
+use axum::http::{header, HeaderMap};
+use image::{imageops::FilterType, ImageFormat, Rgba, RgbaImage};
+use serde::Deserialize;
+
+/// Sizes that can be asked for with `?size=`, any size would fill the cache with one-offs.
+pub const AVATAR_SIZES: [u32; 4] = [32, 64, 128, 256];
+
+/// How long a fetched avatar is served before it's fetched again.
+pub const AVATAR_REFRESH_SECONDS: i64 = 86400;
+
+/// Avatars are kept for a while after they're due, in case GGST can't be reached to refresh them.
+pub const AVATAR_KEEP_SECONDS: i64 = 7 * 86400;
+
+/// How long variants of an avatar that couldn't be refreshed are cached before trying again.
+pub const STALE_RETRY_SECONDS: i64 = 300;
+
+const PLACEHOLDER_SIZE: u32 = 256;
+const PLACEHOLDER_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+#[derive(Deserialize)]
+pub struct AvatarParams {
+    pub size: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AvatarFormat {
+    Png,
+    WebP,
+}
+
+impl AvatarFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            AvatarFormat::Png => "image/png",
+            AvatarFormat::WebP => "image/webp",
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            AvatarFormat::Png => ImageFormat::Png,
+            AvatarFormat::WebP => ImageFormat::WebP,
+        }
+    }
+}
+
+/// WebP for clients that accept it, PNG for everyone else.
+pub fn negotiate_format(request: &HeaderMap) -> AvatarFormat {
+    let accept = match request.get(header::ACCEPT).and_then(|v| v.to_str().ok()) {
+        Some(accept) => accept,
+        None => return AvatarFormat::Png,
+    };
+
+    let accepts_webp = accept.split(',').any(|media_range| {
+        let mut parts = media_range.split(';').map(|p| p.trim());
+        let quality = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        media_range.trim().starts_with("image/webp") && quality > 0.0
+    });
+
+    if accepts_webp {
+        AvatarFormat::WebP
     } else {
-        value
+        AvatarFormat::Png
+    }
+}
+
+pub fn parse_size(size: Option<u32>) -> Result<Option<u32>, String> {
+    match size {
+        Some(size) if !AVATAR_SIZES.contains(&size) => Err(format!(
+            "Unsupported size {size}, use one of {:?}",
+            AVATAR_SIZES
+        )),
+        size => Ok(size),
+    }
+}
+
+/// Names a processed variant in the avatar cache.
+pub fn variant_key(size: Option<u32>, format: AvatarFormat) -> String {
+    let size = match size {
+        Some(size) => size.to_string(),
+        None => "full".to_string(),
+    };
+
+    format!("{size}.{}", format.image_format().extensions_str()[0])
+}
+
+/// Every variant that can be cached for an avatar.
+pub fn all_variant_keys() -> Vec<String> {
+    std::iter::once(None)
+        .chain(AVATAR_SIZES.iter().map(|&size| Some(size)))
+        .flat_map(|size| [AvatarFormat::Png, AvatarFormat::WebP].map(|f| variant_key(size, f)))
+        .collect()
+}
+
+/// Seconds until the stored avatar is due for a refresh, from the TTL it's stored with.
+/// None if it's due now.
+pub fn fresh_for(ttl: i64) -> Option<i64> {
+    let fresh = ttl - (AVATAR_KEEP_SECONDS - AVATAR_REFRESH_SECONDS);
+
+    (fresh > 0).then_some(fresh)
+}
+
+/// The avatar as it's served, from the base64 PNG the game sends.
+pub fn process_avatar(
+    png: &str,
+    size: Option<u32>,
+    format: AvatarFormat,
+) -> Result<Vec<u8>, String> {
+    let png_bytes = match base64_url::decode(png) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(format!("Avatar isn't valid base64: {e}")),
+    };
+
+    let rgba = super::avatar_decode::decode_png(&png_bytes)?;
+
+    encode(rgba, size, format)
+}
+
+/// Served in place of avatars that can't be decoded.
+pub fn placeholder(size: Option<u32>, format: AvatarFormat) -> Vec<u8> {
+    let rgba = RgbaImage::from_pixel(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE, Rgba(PLACEHOLDER_COLOR));
+
+    //Encoding a plain RGBA image into memory doesn't fail
+    encode(rgba, size, format).unwrap_or_default()
+}
+
+fn encode(rgba: RgbaImage, size: Option<u32>, format: AvatarFormat) -> Result<Vec<u8>, String> {
+    let rgba = match size {
+        Some(size) if size != rgba.width() || size != rgba.height() => {
+            image::imageops::resize(&rgba, size, size, FilterType::Lanczos3)
+        }
+        _ => rgba,
+    };
+
+    let mut output = Cursor::new(Vec::new());
+    match rgba.write_to(&mut output, format.image_format()) {
+        Ok(()) => Ok(output.into_inner()),
+        Err(e) => Err(format!("Failed to encode avatar: {e}")),
     }
 }
 
-pub async fn handle_get_avatar(png: String) -> Bytes {
-    // Decode base64
-    let png_bytes = base64_url::decode(&png).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
 
-    // Load image from bytes
-    let img = image::load_from_memory(&png_bytes).unwrap();
+    fn test_avatar() -> String {
+        let rgba = RgbaImage::from_pixel(4, 4, Rgba([64, 128, 255, 0]));
+        let mut png = Cursor::new(Vec::new());
+        rgba.write_to(&mut png, ImageFormat::Png).unwrap();
 
-    // Convert to RGBA
-    let mut rgba = img.to_rgba8();
+        base64_url::encode(&png.into_inner())
+    }
 
-    // Process image
-    for pixel in rgba.pixels_mut() {
-        pixel[0] = process_channel(pixel[0]);
-        pixel[1] = process_channel(pixel[1]);
-        pixel[2] = process_channel(pixel[2]);
-        pixel[3] = 255 - pixel[3];
+    #[test]
+    fn negotiate_format_accept() {
+        let mut request = HeaderMap::new();
+        assert_eq!(negotiate_format(&request), AvatarFormat::Png);
+
+        request.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("image/avif,image/webp,image/png,*/*;q=0.8"),
+        );
+        assert_eq!(negotiate_format(&request), AvatarFormat::WebP);
+
+        request.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("image/webp;q=0, image/png"),
+        );
+        assert_eq!(negotiate_format(&request), AvatarFormat::Png);
+    }
+
+    #[test]
+    fn parse_size_listed_only() {
+        assert_eq!(parse_size(None), Ok(None));
+        assert_eq!(parse_size(Some(64)), Ok(Some(64)));
+        assert!(parse_size(Some(65)).is_err());
     }
 
-    // Convert back to PNG bytes
-    let mut output = Vec::new();
-    let mut cursor = std::io::Cursor::new(&mut output);
-    rgba.write_to(&mut cursor, image::ImageFormat::Png).unwrap();
+    #[test]
+    fn all_variant_keys_every_size_and_format() {
+        let keys = all_variant_keys();
 
-    Bytes::from(output)
+        assert_eq!(keys.len(), (AVATAR_SIZES.len() + 1) * 2);
+        assert!(keys.contains(&variant_key(None, AvatarFormat::Png)));
+        assert!(keys.contains(&variant_key(Some(64), AvatarFormat::WebP)));
+    }
+
+    #[test]
+    fn fresh_for_ttl() {
+        assert_eq!(fresh_for(AVATAR_KEEP_SECONDS), Some(AVATAR_REFRESH_SECONDS));
+        assert_eq!(
+            fresh_for(AVATAR_KEEP_SECONDS - AVATAR_REFRESH_SECONDS),
+            None
+        );
+        //Avatars stored without an expiry
+        assert_eq!(fresh_for(-1), None);
+    }
+
+    #[test]
+    fn process_avatar_resized_webp() {
+        let output = process_avatar(&test_avatar(), Some(32), AvatarFormat::WebP).unwrap();
+
+        let img = image::load_from_memory_with_format(&output, ImageFormat::WebP).unwrap();
+        assert_eq!((img.width(), img.height()), (32, 32));
+    }
+
+    #[test]
+    fn process_avatar_corrupt() {
+        assert!(process_avatar("not an image", None, AvatarFormat::Png).is_err());
+        assert!(process_avatar(&base64_url::encode(b"\x89PNG"), None, AvatarFormat::Png).is_err());
+
+        let img = image::load_from_memory(&placeholder(Some(64), AvatarFormat::Png)).unwrap();
+        assert_eq!((img.width(), img.height()), (64, 64));
+    }
 }
@@ -0,0 +1,111 @@
+use image::{Rgba, RgbaImage};
+
+/// Avatars from the game store alpha inverted, 0 is opaque and 255 is transparent.
+/// Colours are stored squared (gamma 2.0), they're brought back with a square root.
+///
+/// Fully transparent pixels come out as transparent black, the colour the game leaves in them
+/// would otherwise bleed into the edges when the avatar is resized.
+pub fn decode(raw: &RgbaImage) -> RgbaImage {
+    let mut decoded = raw.clone();
+
+    for pixel in decoded.pixels_mut() {
+        *pixel = decode_pixel(*pixel);
+    }
+
+    decoded
+}
+
+/// Decodes the PNG the game sends. Images without an alpha channel can't have been inverted,
+/// they're taken as fully opaque.
+pub fn decode_png(png: &[u8]) -> Result<RgbaImage, String> {
+    let img = match image::load_from_memory(png) {
+        Ok(img) => img,
+        Err(e) => return Err(format!("Avatar isn't a valid image: {e}")),
+    };
+
+    let has_alpha = img.color().has_alpha();
+    let mut raw = img.to_rgba8();
+
+    if !has_alpha {
+        for pixel in raw.pixels_mut() {
+            pixel[3] = 0;
+        }
+    }
+
+    Ok(decode(&raw))
+}
+
+fn decode_pixel(Rgba([r, g, b, a]): Rgba<u8>) -> Rgba<u8> {
+    let alpha = 255 - a;
+
+    if alpha == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    Rgba([
+        decode_channel(r),
+        decode_channel(g),
+        decode_channel(b),
+        alpha,
+    ])
+}
+
+fn decode_channel(value: u8) -> u8 {
+    ((value as f64 / 255.0).sqrt() * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_channel_range() {
+        assert_eq!(decode_channel(0), 0);
+        assert_eq!(decode_channel(4), 32);
+        assert_eq!(decode_channel(16), 64);
+        assert_eq!(decode_channel(64), 128);
+        assert_eq!(decode_channel(255), 255);
+
+        //The curve never darkens a colour
+        assert!((0..=255).all(|v| decode_channel(v) >= v));
+    }
+
+    #[test]
+    fn decode_png_opaque() {
+        let img = decode_png(include_bytes!("testdata/avatar_opaque.png")).unwrap();
+
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(1, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(0, 1), Rgba([128, 64, 32, 255]));
+        assert_eq!(*img.get_pixel(1, 1), Rgba([255, 0, 181, 255]));
+    }
+
+    #[test]
+    fn decode_png_transparent() {
+        let img = decode_png(include_bytes!("testdata/avatar_transparent.png")).unwrap();
+
+        assert!(img.pixels().all(|p| *p == Rgba([0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn decode_png_partial_alpha() {
+        let img = decode_png(include_bytes!("testdata/avatar_partial.png")).unwrap();
+
+        assert_eq!(*img.get_pixel(0, 0), Rgba([128, 128, 128, 127]));
+        assert_eq!(*img.get_pixel(1, 0), Rgba([64, 32, 255, 64]));
+    }
+
+    #[test]
+    fn decode_png_without_alpha() {
+        let img = decode_png(include_bytes!("testdata/avatar_rgb.png")).unwrap();
+
+        assert_eq!(*img.get_pixel(0, 0), Rgba([128, 181, 255, 255]));
+    }
+
+    #[test]
+    fn decode_png_corrupt() {
+        assert!(decode_png(b"\x89PNG\r\n\x1a\n").is_err());
+        assert!(decode_png(&[]).is_err());
+    }
+}
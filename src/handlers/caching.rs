@@ -0,0 +1,216 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDateTime;
+
+/// Stats are updated hourly.
+pub const HOURLY_MAX_AGE: u32 = 300;
+
+/// Popularity, matchups and distribution are updated daily.
+pub const DAILY_MAX_AGE: u32 = 3600;
+
+/// Avatars are refreshed from GGST daily.
+pub const AVATAR_MAX_AGE: u32 = 86400;
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+pub struct CacheValidators {
+    pub etag: String,
+    pub last_modified: Option<NaiveDateTime>,
+}
+
+/// Validators for data that changes whenever `timestamp` does.
+/// `variant` tells apart responses sharing a timestamp, like different patches or characters.
+pub fn timestamp_validators(variant: &str, timestamp: &str) -> CacheValidators {
+    CacheValidators {
+        etag: etag(&(variant, timestamp)),
+        last_modified: NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S").ok(),
+    }
+}
+
+/// Validators for data without a timestamp, the etag is derived from the data itself.
+pub fn content_validators<T: Hash + ?Sized>(content: &T) -> CacheValidators {
+    CacheValidators {
+        etag: etag(&content),
+        last_modified: None,
+    }
+}
+
+fn etag<T: Hash>(value: &T) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("\"{:x}\"", hasher.finish())
+}
+
+/// Whether the client's cached copy is still current.
+/// If-Modified-Since is only used when the client didn't send an If-None-Match.
+pub fn is_not_modified(request: &HeaderMap, validators: &CacheValidators) -> bool {
+    if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+        return match if_none_match.to_str() {
+            Ok(tags) => tags
+                .split(',')
+                .map(|t| t.trim())
+                .any(|t| t == "*" || t.strip_prefix("W/").unwrap_or(t) == validators.etag),
+            Err(_) => false,
+        };
+    }
+
+    let if_modified_since = match request
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| NaiveDateTime::parse_from_str(v, HTTP_DATE).ok())
+    {
+        Some(if_modified_since) => if_modified_since,
+        None => return false,
+    };
+
+    match validators.last_modified {
+        Some(last_modified) => last_modified <= if_modified_since,
+        None => false,
+    }
+}
+
+pub fn cache_headers(validators: &CacheValidators, max_age: u32) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={}", max_age)).unwrap(),
+    );
+    headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&validators.etag).unwrap(),
+    );
+
+    if let Some(last_modified) = validators.last_modified {
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&last_modified.format(HTTP_DATE).to_string()).unwrap(),
+        );
+    }
+
+    headers
+}
+
+/// `response` with caching headers, or an empty 304 if the client's copy is still current.
+pub fn cached_response(
+    request: &HeaderMap,
+    validators: CacheValidators,
+    max_age: u32,
+    response: impl IntoResponse,
+) -> Response {
+    cached_response_with_headers(request, validators, max_age, HeaderMap::new(), response)
+}
+
+/// Same as `cached_response`, with `extra` headers like `Vary` sent on the 304 as well.
+pub fn cached_response_with_headers(
+    request: &HeaderMap,
+    validators: CacheValidators,
+    max_age: u32,
+    extra: HeaderMap,
+    response: impl IntoResponse,
+) -> Response {
+    let mut headers = cache_headers(&validators, max_age);
+    headers.extend(extra);
+
+    if is_not_modified(request, &validators) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    (headers, response).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_validators_variants_differ() {
+        let global = timestamp_validators("stats", "2024-01-01 00:00:00");
+        let patch = timestamp_validators("stats patch_1:", "2024-01-01 00:00:00");
+
+        assert_ne!(global.etag, patch.etag);
+        assert_eq!(global.last_modified, patch.last_modified);
+    }
+
+    #[test]
+    fn is_not_modified_etag() {
+        let validators = timestamp_validators("stats", "2024-01-01 00:00:00");
+
+        let mut request = HeaderMap::new();
+        assert!(!is_not_modified(&request, &validators));
+
+        request.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_str(&format!("\"other\", W/{}", validators.etag)).unwrap(),
+        );
+        assert!(is_not_modified(&request, &validators));
+
+        request.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!is_not_modified(&request, &validators));
+    }
+
+    #[test]
+    fn is_not_modified_last_modified() {
+        let validators = timestamp_validators("stats", "2024-01-01 10:00:00");
+
+        let mut request = HeaderMap::new();
+        request.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Mon, 01 Jan 2024 10:00:00 GMT"),
+        );
+        assert!(is_not_modified(&request, &validators));
+
+        request.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Mon, 01 Jan 2024 09:59:59 GMT"),
+        );
+        assert!(!is_not_modified(&request, &validators));
+    }
+
+    #[test]
+    fn cache_headers_last_modified() {
+        let validators = timestamp_validators("stats", "2024-01-01 10:00:00");
+
+        let headers = cache_headers(&validators, HOURLY_MAX_AGE);
+
+        assert_eq!(
+            headers.get(header::LAST_MODIFIED).unwrap(),
+            "Mon, 01 Jan 2024 10:00:00 GMT"
+        );
+        assert_eq!(
+            headers.get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=300"
+        );
+    }
+
+    #[test]
+    fn cached_response_not_modified_keeps_extra_headers() {
+        let validators = content_validators("avatar");
+        let mut request = HeaderMap::new();
+        request.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_str(&validators.etag).unwrap(),
+        );
+        let mut extra = HeaderMap::new();
+        extra.insert(header::VARY, HeaderValue::from_static("Accept"));
+
+        let response =
+            cached_response_with_headers(&request, validators, AVATAR_MAX_AGE, extra, "avatar");
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::VARY).unwrap(), "Accept");
+    }
+
+    #[test]
+    fn content_validators_no_last_modified() {
+        let validators = content_validators("avatar");
+
+        assert!(validators.last_modified.is_none());
+        assert_eq!(
+            cache_headers(&validators, AVATAR_MAX_AGE).get(header::LAST_MODIFIED),
+            None
+        );
+    }
+}
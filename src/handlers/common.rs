@@ -1,14 +1,51 @@
 use serde::{Deserialize, Serialize};
 
+#[cfg(test)]
+use crate::models::Game;
+
 #[derive(Deserialize)]
 pub struct Pagination {
     pub count: Option<usize>,
     pub offset: Option<usize>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TagResponse {
     pub tag: String,
     pub style: String,
 }
 
+
+#[derive(Deserialize)]
+pub struct PatchParams {
+    pub patch: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PatchResponse {
+    pub version: String,
+    pub label: String,
+    pub start_date: String,
+}
+
+/// Player 1 (id 1, SO, 1000) losing to Player 2 (id 2, KY, `value_b`) just now.
+#[cfg(test)]
+pub fn get_test_game(value_b: i64) -> Game {
+    Game {
+        timestamp: chrono::Utc::now().naive_utc(),
+        id_a: 1,
+        name_a: "Player 1".to_string(),
+        char_a: 0,
+        platform_a: 3,
+        id_b: 2,
+        name_b: "Player 2".to_string(),
+        char_b: 1,
+        platform_b: 3,
+        winner: 2,
+        game_floor: 99,
+        value_a: 1000,
+        value_b,
+        real_timestamp: None,
+        version: None,
+    }
+}
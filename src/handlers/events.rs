@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    characters,
+    models::{Event, Game, NewEvent, RankTier},
+};
+
+/// Ratings above this are vanquisher ratings, see `rating_sync`.
+pub const VANQUISHER_RATING: i64 = 10000000;
+
+pub const PERSONAL_BEST: &str = "personal_best";
+pub const PROMOTION: &str = "promotion";
+pub const DEMOTION: &str = "demotion";
+pub const VANQUISHER: &str = "vanquisher";
+pub const NEW_CHARACTER: &str = "new_character";
+
+/// A player's rating on a character going into a batch of games.
+#[derive(Clone, Copy)]
+pub struct RatingState {
+    pub value: i64,
+    pub best: i64,
+}
+
+#[derive(Serialize)]
+pub struct EventResponse {
+    kind: String,
+    char_short: String,
+    char_name: String,
+    value: i64,
+    previous_value: Option<i64>,
+    tier: Option<String>,
+    timestamp: String,
+}
+
+fn tier_for(value: i64, tiers: &[RankTier]) -> Option<&RankTier> {
+    tiers
+        .iter()
+        .find(|t| value >= t.lower_bound as i64 && value < t.upper_bound as i64)
+}
+
+/// Tiers with an upper bound of 1 or less are placement, moving out of them isn't a promotion.
+fn is_ranked(tier: &RankTier) -> bool {
+    tier.upper_bound > 1
+}
+
+/// Events reached in newly pulled games, oldest game first.
+/// `previous` holds each player's rating per character before these games.
+pub fn detect_events(
+    games: &[Game],
+    previous: &HashMap<(i64, i16), RatingState>,
+    tiers: &[RankTier],
+) -> Vec<NewEvent> {
+    let mut states = previous.clone();
+    let mut result = vec![];
+
+    for game in games {
+        let timestamp = game.real_timestamp.unwrap_or(game.timestamp);
+
+        for (player_id, char_id, value) in [
+            (game.id_a, game.char_a, game.value_a),
+            (game.id_b, game.char_b, game.value_b),
+        ] {
+            let event = |kind: &str, previous_value: Option<i64>, tier: Option<String>| NewEvent {
+                player_id,
+                char_id,
+                kind: kind.to_string(),
+                value,
+                previous_value,
+                tier,
+                timestamp,
+            };
+
+            let state = match states.get(&(player_id, char_id)) {
+                Some(&state) => state,
+                None => {
+                    result.push(event(NEW_CHARACTER, None, None));
+                    states.insert((player_id, char_id), RatingState { value, best: value });
+                    continue;
+                }
+            };
+
+            if value > state.best {
+                result.push(event(PERSONAL_BEST, Some(state.best), None));
+            }
+
+            if state.value <= VANQUISHER_RATING && value > VANQUISHER_RATING {
+                result.push(event(VANQUISHER, Some(state.value), None));
+            }
+
+            if let (Some(before), Some(after)) =
+                (tier_for(state.value, tiers), tier_for(value, tiers))
+                && is_ranked(before)
+                && is_ranked(after)
+                && before.lower_bound != after.lower_bound
+            {
+                let kind = if after.lower_bound > before.lower_bound {
+                    PROMOTION
+                } else {
+                    DEMOTION
+                };
+                result.push(event(kind, Some(state.value), Some(after.name.clone())));
+            }
+
+            states.insert(
+                (player_id, char_id),
+                RatingState {
+                    value,
+                    best: state.best.max(value),
+                },
+            );
+        }
+    }
+
+    result
+}
+
+pub fn event_response(event: Event) -> EventResponse {
+    let character = characters::get(event.char_id);
+
+    EventResponse {
+        kind: event.kind,
+        char_short: character.short,
+        char_name: character.name,
+        value: event.value,
+        previous_value: event.previous_value,
+        tier: event.tier,
+        timestamp: event.timestamp.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::common::get_test_game;
+
+    #[test]
+    fn detect_events_new_character() {
+        let events = detect_events(
+            &[get_test_game(1500)],
+            &get_test_previous(1400, 1600),
+            &get_test_tiers(),
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].player_id, 1);
+        assert_eq!(events[0].kind, NEW_CHARACTER);
+    }
+
+    #[test]
+    fn detect_events_personal_best() {
+        let previous = get_test_previous(1400, 1600);
+
+        let events = detect_events(
+            &[
+                get_test_game(1500),
+                get_test_game(1700),
+                get_test_game(1800),
+            ],
+            &with_player_1(previous),
+            &get_test_tiers(),
+        );
+
+        let bests: Vec<&NewEvent> = events.iter().filter(|e| e.kind == PERSONAL_BEST).collect();
+        assert_eq!(bests.len(), 2);
+        assert_eq!(bests[0].value, 1700);
+        assert_eq!(bests[0].previous_value, Some(1600));
+        assert_eq!(bests[1].previous_value, Some(1700));
+    }
+
+    #[test]
+    fn detect_events_tier_changes() {
+        let previous = get_test_previous(1900, 5000);
+
+        let events = detect_events(
+            &[get_test_game(2100), get_test_game(1950)],
+            &with_player_1(previous),
+            &get_test_tiers(),
+        );
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, PROMOTION);
+        assert_eq!(events[0].tier, Some("Iron 3".to_string()));
+        assert_eq!(events[1].kind, DEMOTION);
+        assert_eq!(events[1].tier, Some("Iron 2".to_string()));
+    }
+
+    #[test]
+    fn detect_events_placement_is_not_a_promotion() {
+        let previous = get_test_previous(0, 0);
+
+        let events = detect_events(
+            &[get_test_game(500)],
+            &with_player_1(previous),
+            &get_test_tiers(),
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, PERSONAL_BEST);
+    }
+
+    #[test]
+    fn detect_events_vanquisher() {
+        let previous = get_test_previous(46000, 10000100);
+
+        let events = detect_events(
+            &[get_test_game(10000050)],
+            &with_player_1(previous),
+            &get_test_tiers(),
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, VANQUISHER);
+        assert_eq!(events[0].previous_value, Some(46000));
+    }
+
+    fn get_test_previous(value: i64, best: i64) -> HashMap<(i64, i16), RatingState> {
+        let mut previous = HashMap::new();
+        previous.insert((2, 1), RatingState { value, best });
+        previous
+    }
+
+    /// Player 1 has played before, with a rating that won't trigger anything.
+    fn with_player_1(
+        mut previous: HashMap<(i64, i16), RatingState>,
+    ) -> HashMap<(i64, i16), RatingState> {
+        previous.insert(
+            (1, 0),
+            RatingState {
+                value: 1000,
+                best: 100000,
+            },
+        );
+        previous
+    }
+
+    fn get_test_tiers() -> Vec<RankTier> {
+        [
+            (-10000000, 1, "Placement"),
+            (1, 1000, "Iron 1"),
+            (1000, 2000, "Iron 2"),
+            (2000, 3000, "Iron 3"),
+            (3000, 45000, "Bronze 1"),
+            (45000, 200000000, "Vanquisher"),
+        ]
+        .into_iter()
+        .map(|(lower_bound, upper_bound, name)| RankTier {
+            lower_bound,
+            upper_bound,
+            name: name.to_string(),
+        })
+        .collect()
+    }
+}
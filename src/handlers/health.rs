@@ -0,0 +1,171 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Replays are pulled every minute, anything older means the pull process is stuck.
+const MAX_PULL_LAG: i64 = 120;
+
+const MAX_HOURLY_AGE: i64 = 2 * 60 * 60;
+
+const MAX_DAILY_AGE: i64 = 24 * 60 * 60;
+
+pub const OK: &str = "ok";
+pub const DEGRADED: &str = "degraded";
+pub const DOWN: &str = "down";
+
+/// Everything the health report is built from, gathered by the caller.
+pub struct HealthChecks {
+    pub postgres: Result<(), String>,
+    pub redis: Result<(), String>,
+    pub ggst_token: bool,
+    pub latest_game_time: Option<NaiveDateTime>,
+    pub last_update_hourly: Option<NaiveDateTime>,
+    pub last_update_daily: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct ComponentStatus {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub message: String,
+    pub postgres: ComponentStatus,
+    pub redis: ComponentStatus,
+    pub ggst_token: ComponentStatus,
+    pub last_replay: Option<String>,
+    pub last_update_hourly: Option<String>,
+    pub last_update_daily: Option<String>,
+    pub pull_lag_seconds: Option<i64>,
+}
+
+fn component(result: Result<(), String>) -> ComponentStatus {
+    match result {
+        Ok(()) => ComponentStatus {
+            ok: true,
+            error: None,
+        },
+        Err(e) => ComponentStatus {
+            ok: false,
+            error: Some(e),
+        },
+    }
+}
+
+/// `status` is down when a store is unreachable and degraded when the data is going stale.
+/// `message` explains the most important problem, for display on the site.
+pub fn health_report(checks: HealthChecks, now: NaiveDateTime) -> HealthResponse {
+    let age = |t: Option<NaiveDateTime>| t.map(|t| (now - t).num_seconds());
+
+    let pull_lag = age(checks.latest_game_time);
+    let hourly_age = age(checks.last_update_hourly);
+    let daily_age = age(checks.last_update_daily);
+
+    //The daily update clears the latest game time, replays come back once it's done
+    let daily_running = daily_age.is_none_or(|a| a > MAX_DAILY_AGE);
+
+    let (status, message) = if let Err(e) = &checks.postgres {
+        (DOWN, format!("Database unavailable: {e}"))
+    } else if let Err(e) = &checks.redis {
+        (DOWN, format!("Redis unavailable: {e}"))
+    } else if pull_lag.is_some_and(|l| l > MAX_PULL_LAG) {
+        (DEGRADED, "No New (2m) Replays!".to_string())
+    } else if daily_running {
+        (
+            DEGRADED,
+            "Daily Update Running. Replays are still being collected and will show up shortly."
+                .to_string(),
+        )
+    } else if pull_lag.is_none() {
+        (DEGRADED, "No replays pulled yet".to_string())
+    } else if hourly_age.is_none_or(|a| a > MAX_HOURLY_AGE) {
+        (DEGRADED, "Hourly update is late".to_string())
+    } else if !checks.ggst_token {
+        (DEGRADED, "GGST is not connected, patch?".to_string())
+    } else {
+        (OK, "OK".to_string())
+    };
+
+    HealthResponse {
+        status: status.to_string(),
+        message,
+        postgres: component(checks.postgres),
+        redis: component(checks.redis),
+        ggst_token: component(if checks.ggst_token {
+            Ok(())
+        } else {
+            Err("token.txt not found".to_string())
+        }),
+        last_replay: checks.latest_game_time.map(|t| t.to_string()),
+        last_update_hourly: checks.last_update_hourly.map(|t| t.to_string()),
+        last_update_daily: checks.last_update_daily.map(|t| t.to_string()),
+        pull_lag_seconds: pull_lag,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_report_ok() {
+        let now = chrono::Utc::now().naive_utc();
+
+        let report = health_report(get_test_checks(now), now);
+
+        assert_eq!(report.status, OK);
+        assert_eq!(report.pull_lag_seconds, Some(30));
+    }
+
+    #[test]
+    fn health_report_redis_down() {
+        let now = chrono::Utc::now().naive_utc();
+        let mut checks = get_test_checks(now);
+        checks.redis = Err("connection refused".to_string());
+
+        let report = health_report(checks, now);
+
+        assert_eq!(report.status, DOWN);
+        assert!(!report.redis.ok);
+        assert!(report.postgres.ok);
+    }
+
+    #[test]
+    fn health_report_stale_replays() {
+        let now = chrono::Utc::now().naive_utc();
+        let mut checks = get_test_checks(now);
+        checks.latest_game_time = Some(now - chrono::Duration::minutes(5));
+
+        let report = health_report(checks, now);
+
+        assert_eq!(report.status, DEGRADED);
+        assert_eq!(report.message, "No New (2m) Replays!");
+    }
+
+    #[test]
+    fn health_report_daily_update_running() {
+        let now = chrono::Utc::now().naive_utc();
+        let mut checks = get_test_checks(now);
+        checks.latest_game_time = None;
+        checks.last_update_daily = Some(now - chrono::Duration::hours(25));
+
+        let report = health_report(checks, now);
+
+        assert_eq!(report.status, DEGRADED);
+        assert!(report.message.starts_with("Daily Update Running."));
+        assert_eq!(report.pull_lag_seconds, None);
+    }
+
+    fn get_test_checks(now: NaiveDateTime) -> HealthChecks {
+        HealthChecks {
+            postgres: Ok(()),
+            redis: Ok(()),
+            ggst_token: true,
+            latest_game_time: Some(now - chrono::Duration::seconds(30)),
+            last_update_hourly: Some(now - chrono::Duration::minutes(30)),
+            last_update_daily: Some(now - chrono::Duration::hours(3)),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{characters, models};
+
+#[derive(Deserialize)]
+pub struct LiveParams {
+    pub player_id: Option<i64>,
+    pub char_id: Option<String>,
+    pub min_rating: Option<i64>,
+}
+
+/// A newly pulled game, as published by the pull process and streamed to clients.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LiveGame {
+    pub timestamp: String,
+    pub floor: i16,
+    pub winner: i16,
+    pub id_a: i64,
+    pub name_a: String,
+    pub char_a: i16,
+    pub char_short_a: String,
+    pub value_a: i64,
+    pub id_b: i64,
+    pub name_b: String,
+    pub char_b: i16,
+    pub char_short_b: String,
+    pub value_b: i64,
+}
+
+pub fn live_game(game: &models::Game) -> LiveGame {
+    LiveGame {
+        timestamp: game.real_timestamp.unwrap_or(game.timestamp).to_string(),
+        floor: game.game_floor,
+        winner: game.winner,
+        id_a: game.id_a,
+        name_a: game.name_a.clone(),
+        char_a: game.char_a,
+        char_short_a: characters::get(game.char_a).short,
+        value_a: game.value_a,
+        id_b: game.id_b,
+        name_b: game.name_b.clone(),
+        char_b: game.char_b,
+        char_short_b: characters::get(game.char_b).short,
+        value_b: game.value_b,
+    }
+}
+
+/// Whether a game passes every filter the client asked for.
+/// The rating filter applies to the higher rated side, so either player can meet it.
+pub fn matches_filter(
+    game: &LiveGame,
+    player_id: Option<i64>,
+    char_id: Option<i16>,
+    min_rating: Option<i64>,
+) -> bool {
+    player_id.is_none_or(|id| game.id_a == id || game.id_b == id)
+        && char_id.is_none_or(|c| game.char_a == c || game.char_b == c)
+        && min_rating.is_none_or(|r| game.value_a.max(game.value_b) >= r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_filter_no_filters() {
+        assert!(matches_filter(&get_test_live_game(), None, None, None));
+    }
+
+    #[test]
+    fn matches_filter_player() {
+        let game = get_test_live_game();
+
+        assert!(matches_filter(&game, Some(2), None, None));
+        assert!(!matches_filter(&game, Some(3), None, None));
+    }
+
+    #[test]
+    fn matches_filter_character() {
+        let game = get_test_live_game();
+
+        assert!(matches_filter(&game, None, Some(0), None));
+        assert!(matches_filter(&game, None, Some(1), None));
+        assert!(!matches_filter(&game, None, Some(2), None));
+    }
+
+    #[test]
+    fn matches_filter_min_rating() {
+        let game = get_test_live_game();
+
+        assert!(matches_filter(&game, None, None, Some(15000)));
+        assert!(!matches_filter(&game, None, None, Some(15001)));
+        assert!(!matches_filter(&game, Some(1), Some(0), Some(20000)));
+    }
+
+    fn get_test_live_game() -> LiveGame {
+        LiveGame {
+            timestamp: "2024-01-01 00:00:00".to_string(),
+            floor: 99,
+            winner: 1,
+            id_a: 1,
+            name_a: "Player 1".to_string(),
+            char_a: 0,
+            char_short_a: "SO".to_string(),
+            value_a: 12000,
+            id_b: 2,
+            name_b: "Player 2".to_string(),
+            char_b: 1,
+            char_short_b: "KY".to_string(),
+            value_b: 15000,
+        }
+    }
+}
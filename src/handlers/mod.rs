@@ -4,4 +4,13 @@ pub mod player_history;
 pub mod top;
 pub mod search;
 pub mod avatar;
-pub mod rating_sync;
\ No newline at end of file
+pub mod rating_sync;
+pub mod percentile;
+pub mod trends;
+pub mod live;
+pub mod webhooks;
+pub mod events;
+pub mod caching;
+pub mod health;
+pub mod rate_limit;
+pub mod avatar_decode;
\ No newline at end of file
@@ -0,0 +1,106 @@
+use serde::Serialize;
+
+use crate::{characters, db::PercentileResult};
+
+#[derive(Serialize)]
+pub struct PercentileResponse {
+    id: i64,
+    char_short: String,
+    character: String,
+    rating: i64,
+    tier: String,
+    percentile: f64,
+    ranked_players: i64,
+}
+
+/// Percentage of ranked players on the same character with a lower rating.
+/// Placement players (rating below 1) are always at 0.
+pub fn calc_percentile(value: i64, below: i64, total: i64) -> f64 {
+    if value < 1 || total == 0 {
+        return 0.0;
+    }
+
+    (below as f64 * 10000.0 / total as f64).round() / 100.0
+}
+
+pub async fn handle_get_percentile(
+    player_id: i64,
+    percentile: PercentileResult,
+) -> Result<PercentileResponse, String> {
+    let character = characters::get(percentile.char_id);
+
+    Ok(PercentileResponse {
+        id: player_id,
+        char_short: character.short,
+        character: character.name,
+        rating: percentile.value,
+        tier: percentile.tier,
+        percentile: calc_percentile(percentile.value, percentile.below, percentile.total),
+        ranked_players: percentile.total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calc_percentile_placement() {
+        assert_eq!(calc_percentile(0, 0, 100), 0.0);
+    }
+
+    #[test]
+    fn calc_percentile_no_players() {
+        assert_eq!(calc_percentile(5000, 0, 0), 0.0);
+    }
+
+    #[test]
+    fn calc_percentile_rounds() {
+        assert_eq!(calc_percentile(5000, 1, 3), 33.33);
+        assert_eq!(calc_percentile(5000, 2, 3), 66.67);
+    }
+
+    #[tokio::test]
+    async fn get_percentile_response() {
+        characters::set_test_characters();
+
+        let response = handle_get_percentile(
+            1,
+            PercentileResult {
+                char_id: 0,
+                value: 13500,
+                tier: "Gold 1".to_string(),
+                below: 50,
+                total: 200,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.char_short, "SO");
+        assert_eq!(response.tier, "Gold 1");
+        assert_eq!(response.percentile, 25.0);
+        assert_eq!(response.ranked_players, 200);
+    }
+
+    #[tokio::test]
+    async fn get_percentile_unknown_character() {
+        characters::set_test_characters();
+
+        let response = handle_get_percentile(
+            1,
+            PercentileResult {
+                char_id: 99,
+                value: 13500,
+                tier: "Gold 1".to_string(),
+                below: 50,
+                total: 200,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.char_short, "U99");
+        assert_eq!(response.character, "Unknown (99)");
+    }
+}
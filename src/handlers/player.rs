@@ -1,12 +1,12 @@
 use std::collections::HashMap;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{models::{Player, PlayerRating}, CHAR_NAMES};
+use crate::{characters, models::{Game, Player, PlayerCharStat, PlayerOfficialStat, PlayerRating}};
 
 use super::common::TagResponse;
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct PlayerResponse {
     id: i64,
     name: String,
@@ -16,7 +16,7 @@ pub struct PlayerResponse {
     tags: Vec<TagResponse>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct PlayerResponsePlayer {
     rating: i64,
     char_short: String,
@@ -25,9 +25,21 @@ struct PlayerResponsePlayer {
     top_char: i32,
     top_defeated: TopDefeated,
     top_rating: TopRating,
+    tier: String,
+    percentile: f64,
+    /// Totals from the game's own stats, only known for players that have been synced.
+    official: Option<OfficialStats>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OfficialStats {
+    pub level: Option<i32>,
+    pub ranked_games: Option<i32>,
+    pub ranked_wins: Option<i32>,
+    pub updated_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TopDefeated {
     pub timestamp: String,
     pub id: i64,
@@ -36,27 +48,36 @@ pub struct TopDefeated {
     pub value: i64,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TopRating {
     pub timestamp: String,
     pub value: i64,
 }
 
+#[derive(Serialize, Clone)]
+pub struct Percentile {
+    pub tier: String,
+    pub percentile: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_get_player(
     player_char: Vec<(Player, PlayerRating)>,
     match_counts: HashMap<i16, i32>,
     top_chars: HashMap<i16, i32>,
     top_defeated: HashMap<i16, TopDefeated>,
     top_rating: HashMap<i16, TopRating>,
+    percentiles: HashMap<i16, Percentile>,
     top_global: i32,
     tags: Vec<(String, String)>,
+    official_stats: HashMap<i16, PlayerOfficialStat>,
 ) -> Result<PlayerResponse, String> {
     let ratings: Vec<PlayerResponsePlayer> = player_char
         .iter()
         .map(|p| PlayerResponsePlayer {
             rating: p.1.value,
-            char_short: CHAR_NAMES[p.1.char_id as usize].0.to_string(),
-            character: CHAR_NAMES[p.1.char_id as usize].1.to_string(),
+            char_short: characters::get(p.1.char_id).short,
+            character: characters::get(p.1.char_id).name,
             match_count: match_counts.get(&p.1.char_id).unwrap().clone(),
             top_char: top_chars.get(&p.1.char_id).unwrap().clone(),
             top_defeated: top_defeated
@@ -76,6 +97,20 @@ pub async fn handle_get_player(
                     value: 0,
                 })
                 .clone(),
+            tier: percentiles
+                .get(&p.1.char_id)
+                .map(|p| p.tier.clone())
+                .unwrap_or("N/A".to_string()),
+            percentile: percentiles
+                .get(&p.1.char_id)
+                .map(|p| p.percentile)
+                .unwrap_or(0.0),
+            official: official_stats.get(&p.1.char_id).map(|s| OfficialStats {
+                level: s.level,
+                ranked_games: s.ranked_games,
+                ranked_wins: s.ranked_wins,
+                updated_at: s.updated_at.to_string(),
+            }),
         })
         .collect();
 
@@ -100,14 +135,52 @@ pub async fn handle_get_player(
     })
 }
 
+/// What a single game adds to each player's `player_char_stats` row, player a first.
+pub fn game_char_stats(game: &Game) -> [PlayerCharStat; 2] {
+    [
+        (1, game.id_a, game.char_a, game.value_a, game.id_b, &game.name_b, game.char_b, game.value_b),
+        (2, game.id_b, game.char_b, game.value_b, game.id_a, &game.name_a, game.char_a, game.value_a),
+    ]
+    .map(|(side, id, char_id, value, opponent_id, opponent_name, opponent_char, opponent_value)| {
+        let won = game.winner == side;
+
+        PlayerCharStat {
+            id,
+            char_id,
+            games: 1,
+            wins: won as i64,
+            losses: !won as i64,
+            peak_rating: value,
+            peak_timestamp: game.timestamp,
+            top_defeated_id: won.then_some(opponent_id),
+            top_defeated_name: won.then(|| opponent_name.clone()),
+            top_defeated_char: won.then_some(opponent_char),
+            top_defeated_value: won.then_some(opponent_value),
+            top_defeated_timestamp: won.then_some(game.timestamp),
+            first_played: game.timestamp,
+            last_played: game.timestamp,
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::handlers::common::get_test_game;
 
     #[tokio::test]
     async fn get_player_empty_top_defeated() {
-        let (player_char, match_counts, top_chars, _top_defeated, top_rating, top_global, tags) =
-            get_test_player_data();
+        let (
+            player_char,
+            match_counts,
+            top_chars,
+            _top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
 
         let top_defeated = HashMap::new();
 
@@ -117,8 +190,10 @@ mod tests {
             top_chars,
             top_defeated,
             top_rating,
+            percentiles,
             top_global,
             tags,
+            official_stats,
         )
         .await
         .unwrap();
@@ -129,8 +204,17 @@ mod tests {
 
     #[tokio::test]
     async fn get_player_empty_top_rating() {
-        let (player_char, match_counts, top_chars, top_defeated, _top_rating, top_global, tags) =
-            get_test_player_data();
+        let (
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            _top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
 
         let top_rating = HashMap::new();
 
@@ -140,8 +224,10 @@ mod tests {
             top_chars,
             top_defeated,
             top_rating,
+            percentiles,
             top_global,
             tags,
+            official_stats,
         )
         .await
         .unwrap();
@@ -152,8 +238,17 @@ mod tests {
 
     #[tokio::test]
     async fn get_player_platform_ps() {
-        let (mut player_char, match_counts, top_chars, top_defeated, top_rating, top_global, tags) =
-            get_test_player_data();
+        let (
+            mut player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
 
         player_char[0].0.platform = 1;
 
@@ -163,8 +258,10 @@ mod tests {
             top_chars,
             top_defeated,
             top_rating,
+            percentiles,
             top_global,
             tags,
+            official_stats,
         )
         .await
         .unwrap();
@@ -174,8 +271,17 @@ mod tests {
 
     #[tokio::test]
     async fn get_player_platform_xb() {
-        let (mut player_char, match_counts, top_chars, top_defeated, top_rating, top_global, tags) =
-            get_test_player_data();
+        let (
+            mut player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
 
         player_char[0].0.platform = 2;
 
@@ -185,8 +291,10 @@ mod tests {
             top_chars,
             top_defeated,
             top_rating,
+            percentiles,
             top_global,
             tags,
+            official_stats,
         )
         .await
         .unwrap();
@@ -196,8 +304,17 @@ mod tests {
 
     #[tokio::test]
     async fn get_player_platform_pc() {
-        let (mut player_char, match_counts, top_chars, top_defeated, top_rating, top_global, tags) =
-            get_test_player_data();
+        let (
+            mut player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
 
         player_char[0].0.platform = 3;
 
@@ -207,8 +324,10 @@ mod tests {
             top_chars,
             top_defeated,
             top_rating,
+            percentiles,
             top_global,
             tags,
+            official_stats,
         )
         .await
         .unwrap();
@@ -216,14 +335,154 @@ mod tests {
         assert_eq!(response.platform, "PC");
     }
 
+    #[tokio::test]
+    async fn get_player_percentile() {
+        let (
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            mut percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
+
+        percentiles.insert(
+            0,
+            Percentile {
+                tier: "Iron 2".to_string(),
+                percentile: 12.5,
+            },
+        );
+
+        let response = handle_get_player(
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.ratings[0].tier, "Iron 2");
+        assert_eq!(response.ratings[0].percentile, 12.5);
+    }
+
+    #[tokio::test]
+    async fn get_player_cache_round_trip() {
+        let (
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        ) = get_test_player_data();
+
+        let response = handle_get_player(
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        )
+        .await
+        .unwrap();
+
+        //The player cache stores responses as json
+        let json = serde_json::to_string(&response).unwrap();
+        let cached: PlayerResponse = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(serde_json::to_string(&cached).unwrap(), json);
+    }
+
+    #[tokio::test]
+    async fn get_player_official_stats() {
+        let (
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            _official_stats,
+        ) = get_test_player_data();
+
+        let mut official_stats = HashMap::new();
+        official_stats.insert(
+            0,
+            PlayerOfficialStat {
+                id: 1,
+                char_id: 0,
+                level: Some(45),
+                ranked_games: Some(812),
+                ranked_wins: None,
+                updated_at: chrono::NaiveDateTime::default(),
+            },
+        );
+
+        let response = handle_get_player(
+            player_char,
+            match_counts,
+            top_chars,
+            top_defeated,
+            top_rating,
+            percentiles,
+            top_global,
+            tags,
+            official_stats,
+        )
+        .await
+        .unwrap();
+
+        let official = response.ratings[0].official.as_ref().unwrap();
+        assert_eq!(official.level, Some(45));
+        assert_eq!(official.ranked_games, Some(812));
+        assert_eq!(official.ranked_wins, None);
+    }
+
+    #[test]
+    fn game_char_stats_sides() {
+        let game = get_test_game(1500);
+
+        let [a, b] = game_char_stats(&game);
+
+        assert_eq!((a.id, a.char_id, a.wins, a.losses), (1, 0, 0, 1));
+        assert_eq!(a.top_defeated_id, None);
+        assert_eq!(a.peak_rating, 1000);
+
+        assert_eq!((b.id, b.char_id, b.wins, b.losses), (2, 1, 1, 0));
+        assert_eq!(b.top_defeated_id, Some(1));
+        assert_eq!(b.top_defeated_value, Some(1000));
+        assert_eq!(b.peak_rating, 1500);
+    }
+
     fn get_test_player_data() -> (
         Vec<(Player, PlayerRating)>,
         HashMap<i16, i32>,
         HashMap<i16, i32>,
         HashMap<i16, TopDefeated>,
         HashMap<i16, TopRating>,
+        HashMap<i16, Percentile>,
         i32,
         Vec<(String, String)>,
+        HashMap<i16, PlayerOfficialStat>,
     ) {
         let player_char = vec![(
             Player {
@@ -247,8 +506,10 @@ mod tests {
 
         let top_defeated = HashMap::new();
         let top_rating = HashMap::new();
+        let percentiles = HashMap::new();
         let top_global = 0;
         let tags = vec![];
+        let official_stats = HashMap::new();
 
         (
             player_char,
@@ -256,8 +517,10 @@ mod tests {
             top_chars,
             top_defeated,
             top_rating,
+            percentiles,
             top_global,
             tags,
+            official_stats,
         )
     }
 }
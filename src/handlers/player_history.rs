@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use serde::Serialize;
 
-use crate::{models, CHAR_NAMES};
+use crate::{characters, models};
 
 use super::common::TagResponse;
 
@@ -20,8 +20,8 @@ struct PlayerSet {
     opponent_name: String,
     opponent_platform: &'static str,
     opponent_id: i64,
-    opponent_character: &'static str,
-    opponent_character_short: &'static str,
+    opponent_character: String,
+    opponent_character_short: String,
     opponent_rating_value: i64,
     result_win: bool,
 }
@@ -62,15 +62,9 @@ pub async fn handle_get_player_history(
         };
 
         let opponent_character = if game.id_a == player_id {
-            CHAR_NAMES[game.char_b as usize].1
+            characters::get(game.char_b)
         } else {
-            CHAR_NAMES[game.char_a as usize].1
-        };
-
-        let opponent_character_short = if game.id_a == player_id {
-            CHAR_NAMES[game.char_b as usize].0
-        } else {
-            CHAR_NAMES[game.char_a as usize].0
+            characters::get(game.char_a)
         };
 
         let opponent_rating_value = if game.id_a == player_id {
@@ -106,8 +100,8 @@ pub async fn handle_get_player_history(
                 _ => "??",
             },
             opponent_id: opponent_id,
-            opponent_character,
-            opponent_character_short,
+            opponent_character: opponent_character.name,
+            opponent_character_short: opponent_character.short,
             opponent_rating_value: opponent_rating_value,
             result_win,
         });
@@ -239,6 +233,7 @@ mod tests {
           value_b: 2000,
           timestamp: chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
           real_timestamp: None,
+          version: None,
           game_floor: 1,
           winner: 1,
         },
@@ -255,6 +250,7 @@ mod tests {
           value_b: 2000,
           timestamp: chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
           real_timestamp: None,
+          version: None,
           game_floor: 1,
           winner: 2,
         },
@@ -0,0 +1,151 @@
+use std::net::IpAddr;
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use crate::config::{RateLimitConfig, RateLimitGroup};
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Group every request is counted against when its route isn't listed elsewhere.
+pub const DEFAULT_GROUP: &str = "default";
+
+/// The group limiting `route`, None for exempt routes or when there's no default group.
+pub fn route_group<'a>(
+    config: &'a RateLimitConfig,
+    route: &str,
+) -> Option<(&'a str, &'a RateLimitGroup)> {
+    if config.exempt_routes.iter().any(|r| r == route) {
+        return None;
+    }
+
+    match config
+        .groups
+        .iter()
+        .find(|(_, group)| group.routes.iter().any(|r| r == route))
+    {
+        Some((name, group)) => Some((name.as_str(), group)),
+        None => config
+            .groups
+            .get_key_value(DEFAULT_GROUP)
+            .map(|(name, group)| (name.as_str(), group)),
+    }
+}
+
+/// Who a request is counted against: a known api key, otherwise the client's address.
+/// Unknown keys are ignored so they can't be used to get a fresh bucket.
+pub fn client_key(
+    config: &RateLimitConfig,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Option<String> {
+    if let Some(key) = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok())
+        && config.api_keys.iter().any(|k| k == key)
+    {
+        return Some(format!("key:{key}"));
+    }
+
+    let forwarded = if config.client_ip_header.is_empty() {
+        None
+    } else {
+        headers
+            .get(config.client_ip_header.as_str())
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<IpAddr>().ok())
+    };
+
+    forwarded.or(peer).map(|ip| format!("ip:{ip}"))
+}
+
+pub fn too_many_requests(retry_after: u64) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::RETRY_AFTER,
+        HeaderValue::from_str(&retry_after.to_string()).unwrap(),
+    );
+
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        headers,
+        format!("Too many requests, try again in {retry_after}s"),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_group_listed_default_and_exempt() {
+        let config = RateLimitConfig::default();
+
+        let (name, group) = route_group(&config, "/api/player/search").unwrap();
+        assert_eq!(name, "heavy");
+        assert_eq!(group.capacity, 20);
+
+        let (name, _) = route_group(&config, "/api/top").unwrap();
+        assert_eq!(name, DEFAULT_GROUP);
+
+        assert!(route_group(&config, "/api/live").is_none());
+    }
+
+    #[test]
+    fn route_group_without_default() {
+        let mut config = RateLimitConfig::default();
+        config.groups.remove(DEFAULT_GROUP);
+
+        assert!(route_group(&config, "/api/top").is_none());
+        assert!(route_group(&config, "/api/player/search").is_some());
+    }
+
+    #[test]
+    fn client_key_sources() {
+        let config = RateLimitConfig {
+            client_ip_header: "X-Real-IP".to_string(),
+            api_keys: vec!["bot".to_string()],
+            ..RateLimitConfig::default()
+        };
+        let peer = Some("127.0.0.1".parse().unwrap());
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_key(&config, &headers, peer).unwrap(), "ip:127.0.0.1");
+
+        headers.insert("X-Real-IP", HeaderValue::from_static("203.0.113.7"));
+        assert_eq!(
+            client_key(&config, &headers, peer).unwrap(),
+            "ip:203.0.113.7"
+        );
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("unknown"));
+        assert_eq!(
+            client_key(&config, &headers, peer).unwrap(),
+            "ip:203.0.113.7"
+        );
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("bot"));
+        assert_eq!(client_key(&config, &headers, peer).unwrap(), "key:bot");
+    }
+
+    #[test]
+    fn client_key_header_not_trusted_by_default() {
+        let config = RateLimitConfig::default();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Real-IP", HeaderValue::from_static("203.0.113.7"));
+
+        assert_eq!(
+            client_key(&config, &headers, Some("10.0.0.1".parse().unwrap())).unwrap(),
+            "ip:10.0.0.1"
+        );
+        assert!(client_key(&config, &headers, None).is_none());
+    }
+
+    #[test]
+    fn too_many_requests_retry_after() {
+        let response = too_many_requests(12);
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "12");
+    }
+}
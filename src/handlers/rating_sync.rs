@@ -1,23 +1,105 @@
+use std::collections::HashMap;
+
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use crate::{CHAR_NAMES, db};
+use crate::{characters, db, models::PlayerOfficialStat};
 
-pub async fn parse_player_stats_and_update_ratings(
-    player_id: i64,
-    json_data: &str,
-    db: &mut crate::Connection<'_>,
-) -> Result<Vec<(i16, i64)>, String> {
-    let parsed: Value = match serde_json::from_str(json_data) {
-        Ok(parsed) => parsed,
-        Err(e) => return Err(format!("Failed to parse JSON: {}", e)),
+/// How long a player has to wait between syncs.
+pub const RATING_SYNC_COOLDOWN: u64 = 60;
+
+/// Players that can wait for a sync at once, more would take the pull process hours to get through.
+pub const RATING_SYNC_QUEUE_MAX: usize = 1000;
+
+pub const QUEUED: &str = "queued";
+pub const RUNNING: &str = "running";
+pub const DONE: &str = "done";
+pub const FAILED: &str = "failed";
+
+/// Per-character keys of the official totals, prefixed with the character's json code.
+const LEVEL_SUFFIX: &str = "_Lv";
+const RANKED_GAMES_SUFFIX: &str = "_RankMatchPlayCount";
+const RANKED_WINS_SUFFIX: &str = "_RankMatchWinCount";
+
+/// Progress of a player's queued sync, polled by the player page.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RatingSyncStatus {
+    pub status: String,
+    pub message: String,
+    pub changes: Option<RatingSyncDiff>,
+    pub updated_at: String,
+}
+
+/// What a sync changed. `previous` is None for values the player didn't have yet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Change<T> {
+    pub previous: Option<T>,
+    pub value: T,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RatingChange {
+    pub char_id: i16,
+    pub char_short: String,
+    pub char_name: String,
+    pub previous: Option<i64>,
+    pub value: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RatingSyncDiff {
+    pub name: Option<Change<String>>,
+    pub platform: Option<Change<i16>>,
+    pub ratings: Vec<RatingChange>,
+    /// Characters whose official totals were stored.
+    pub official_stats: usize,
+    pub avatar_refreshed: bool,
+}
+
+pub fn rating_sync_status(status: &str, message: &str) -> RatingSyncStatus {
+    RatingSyncStatus {
+        status: status.to_string(),
+        message: message.to_string(),
+        changes: None,
+        updated_at: chrono::Utc::now().naive_utc().to_string(),
+    }
+}
+
+/// The status a sync ends with, from what it changed or the reason it failed.
+pub fn finished_status(result: Result<RatingSyncDiff, String>) -> RatingSyncStatus {
+    let diff = match result {
+        Ok(diff) => diff,
+        Err(e) => return rating_sync_status(FAILED, &e),
+    };
+
+    let mut message = if diff.ratings.is_empty() {
+        "No ratings to update".to_string()
+    } else {
+        format!("Updated {} character ratings", diff.ratings.len())
     };
+    if let Some(name) = &diff.name {
+        message.push_str(&format!(", name is now {}", name.value));
+    }
+
+    let mut status = rating_sync_status(DONE, &message);
+    status.changes = Some(diff);
+    status
+}
+
+/// Each character's rating in the stats payload, master ratings are offset into the vanquisher range.
+pub fn parse_ratings(parsed: &Value) -> Vec<(i16, i64)> {
+    let mut ratings = Vec::new();
 
-    let mut updated_ratings = Vec::new();
+    for character in characters::all().iter() {
+        //Placeholder characters don't have a known stats code yet
+        if character.json_code.is_empty() {
+            continue;
+        }
 
-    for (char_index, (char_code, _char_name)) in CHAR_NAMES.iter().enumerate() {
-        let json_char_code = get_json_char_code(char_code);
+        let json_char_code = &character.json_code;
         let master_rating_key = format!("{}_MasterRatingPt", json_char_code);
         let rank_match_rating_key = format!("{}_RankMatchRatingPt", json_char_code);
-        
+
         let rating = if let Some(master_rating_value) = parsed.get(&master_rating_key) {
             if let Some(master_rating) = master_rating_value.as_i64() {
                 if master_rating > 0 {
@@ -40,55 +122,283 @@ pub async fn parse_player_stats_and_update_ratings(
                 .and_then(|v| v.as_i64())
                 .filter(|&r| r > 0)
         };
-        
+
         if let Some(rating_value) = rating {
-            let char_id = char_index as i16;
-            match db::set_player_rating(player_id, char_id, rating_value, db).await {
-                Ok(()) => {
-                    updated_ratings.push((char_id, rating_value));
-                },
-                Err(e) => return Err(format!("Failed to update rating for character {}: {}", char_code, e)),
+            ratings.push((character.id, rating_value));
+        }
+    }
+
+    ratings
+}
+
+/// The official totals for every character the payload has any for.
+pub fn parse_official_stats(
+    player_id: i64,
+    parsed: &Value,
+    updated_at: chrono::NaiveDateTime,
+) -> Vec<PlayerOfficialStat> {
+    let get = |code: &str, suffix: &str| {
+        parsed
+            .get(format!("{code}{suffix}"))
+            .and_then(|v| v.as_i64())
+            .and_then(|v| i32::try_from(v).ok())
+    };
+
+    characters::all()
+        .iter()
+        .filter(|c| !c.json_code.is_empty())
+        .map(|c| PlayerOfficialStat {
+            id: player_id,
+            char_id: c.id,
+            level: get(&c.json_code, LEVEL_SUFFIX),
+            ranked_games: get(&c.json_code, RANKED_GAMES_SUFFIX),
+            ranked_wins: get(&c.json_code, RANKED_WINS_SUFFIX),
+            updated_at,
+        })
+        .filter(|s| s.level.is_some() || s.ranked_games.is_some() || s.ranked_wins.is_some())
+        .collect()
+}
+
+/// Ratings that differ from what's stored, including characters the player has no rating for yet.
+pub fn rating_changes(current: &HashMap<i16, i64>, synced: &[(i16, i64)]) -> Vec<RatingChange> {
+    synced
+        .iter()
+        .filter(|(char_id, value)| current.get(char_id) != Some(value))
+        .map(|&(char_id, value)| {
+            let character = characters::get(char_id);
+            RatingChange {
+                char_id,
+                char_short: character.short,
+                char_name: character.name,
+                previous: current.get(&char_id).copied(),
+                value,
             }
+        })
+        .collect()
+}
+
+/// The next `count` players after `cursor` in the sorted `candidates`, wrapping around to the
+/// start so every candidate gets its turn.
+pub fn next_refresh_batch(candidates: &[i64], cursor: i64, count: usize) -> Vec<i64> {
+    let start = candidates.partition_point(|&id| id <= cursor);
+
+    candidates[start..]
+        .iter()
+        .chain(candidates[..start].iter())
+        .take(count)
+        .copied()
+        .collect()
+}
+
+fn change<T: PartialEq>(previous: T, value: T) -> Option<Change<T>> {
+    if previous == value {
+        return None;
+    }
+
+    Some(Change {
+        previous: Some(previous),
+        value,
+    })
+}
+
+/// Rolls the sync back, with the reason it failed.
+struct SyncError(String);
+
+impl From<diesel::result::Error> for SyncError {
+    fn from(e: diesel::result::Error) -> Self {
+        SyncError(e.to_string())
+    }
+}
+
+/// Brings a known player's ratings and official totals in line with their in-game stats and
+/// their name and platform in line with their newest replay, all of it or nothing.
+pub async fn sync_player_stats(
+    player_id: i64,
+    json_data: &str,
+    db: &mut AsyncPgConnection,
+) -> Result<RatingSyncDiff, String> {
+    let parsed: Value = match serde_json::from_str(json_data) {
+        Ok(parsed) => parsed,
+        Err(e) => return Err(format!("Failed to parse JSON: {}", e)),
+    };
+
+    let result = db
+        .transaction::<_, SyncError, _>(|conn| {
+            async move {
+                apply_player_stats(player_id, &parsed, conn)
+                    .await
+                    .map_err(SyncError)
+            }
+            .scope_boxed()
+        })
+        .await;
+
+    result.map_err(|e| e.0)
+}
+
+async fn apply_player_stats(
+    player_id: i64,
+    parsed: &Value,
+    db: &mut AsyncPgConnection,
+) -> Result<RatingSyncDiff, String> {
+    //Syncs only update players we've seen in a replay
+    let player = match db::get_player(player_id, db).await? {
+        Some(player) => player,
+        None => return Err("Player not found".to_string()),
+    };
+
+    let mut diff = RatingSyncDiff::default();
+
+    //The stats payload has neither, replays are processed out of order so the player row
+    //can be left with an older name
+    if let Some((name, platform)) = db::get_latest_profile(player_id, db).await? {
+        diff.name = change(player.name, name.clone());
+        diff.platform = change(player.platform, platform);
+        db::update_player_profile(player_id, &name, platform, db).await?;
+    }
+
+    let official_stats = parse_official_stats(player_id, parsed, chrono::Utc::now().naive_utc());
+    db::set_official_stats(&official_stats, db).await?;
+    diff.official_stats = official_stats.len();
+
+    let current = db::get_player_ratings(player_id, db).await?;
+    diff.ratings = rating_changes(&current, &parse_ratings(parsed));
+
+    for rating in &diff.ratings {
+        if let Err(e) = db::set_player_rating(player_id, rating.char_id, rating.value, db).await {
+            return Err(format!(
+                "Failed to update rating for character {}: {}",
+                rating.char_short, e
+            ));
         }
     }
 
-    Ok(updated_ratings)
+    Ok(diff)
 }
 
-fn get_json_char_code(char_code: &str) -> String {
-    match char_code {
-        "SO" => "SOL".to_string(),
-        "KY" => "KYK".to_string(),
-        "MA" => "MAY".to_string(),
-        "AX" => "AXL".to_string(),
-        "CH" => "CHP".to_string(),
-        "PO" => "POT".to_string(),
-        "FA" => "FAU".to_string(),
-        "MI" => "MLL".to_string(),
-        "ZA" => "ZAT".to_string(),
-        "RA" => "RAM".to_string(),
-        "LE" => "LEO".to_string(),
-        "NA" => "NAG".to_string(),
-        "GI" => "GIO".to_string(),
-        "AN" => "ANJ".to_string(),
-        "IN" => "INO".to_string(),
-        "GO" => "GLD".to_string(),
-        "JC" => "JKO".to_string(),
-        "HA" => "COS".to_string(),
-        "BA" => "BKN".to_string(),
-        "TE" => "TST".to_string(),
-        "BI" => "BGT".to_string(),
-        "SI" => "SIN".to_string(),
-        "BE" => "BED".to_string(),
-        "AS" => "ASK".to_string(),
-        "JN" => "JHN".to_string(),
-        "EL" => "ELP".to_string(),
-        "AB" => "ABA".to_string(),
-        "SL" => "SLY".to_string(),
-        "DI" => "DZY".to_string(),
-        "VE" => "VEN".to_string(),
-        "UN" => "UNI".to_string(),
-        "LU" => "LUC".to_string(),
-        _ => char_code.to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finished_status_updated() {
+        let diff = RatingSyncDiff {
+            name: Some(Change {
+                previous: Some("Old".to_string()),
+                value: "New".to_string(),
+            }),
+            ratings: vec![RatingChange {
+                char_id: 0,
+                char_short: "SO".to_string(),
+                char_name: "Sol".to_string(),
+                previous: None,
+                value: 1500,
+            }],
+            ..Default::default()
+        };
+
+        let status = finished_status(Ok(diff.clone()));
+
+        assert_eq!(status.status, DONE);
+        assert_eq!(status.message, "Updated 1 character ratings, name is now New");
+        assert_eq!(status.changes, Some(diff));
+    }
+
+    #[test]
+    fn finished_status_nothing_or_failed() {
+        let status = finished_status(Ok(RatingSyncDiff::default()));
+        assert_eq!(status.status, DONE);
+        assert_eq!(status.message, "No ratings to update");
+
+        let status = finished_status(Err("Failed to get player stats".to_string()));
+        assert_eq!(status.status, FAILED);
+        assert_eq!(status.changes, None);
+        assert_eq!(status.message, "Failed to get player stats");
+    }
+
+    #[test]
+    fn parse_ratings_master_and_rank_match() {
+        characters::set_test_characters();
+
+        let parsed: Value = serde_json::from_str(
+            r#"{
+                "SOL_MasterRatingPt": 1200,
+                "SOL_RankMatchRatingPt": 45000,
+                "KYK_MasterRatingPt": 0,
+                "KYK_RankMatchRatingPt": 1500,
+                "MAY_RankMatchRatingPt": 0
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(parse_ratings(&parsed), vec![(0, 10001200), (1, 1500)]);
+    }
+
+    #[test]
+    fn parse_official_stats_partial() {
+        characters::set_test_characters();
+
+        let parsed: Value = serde_json::from_str(
+            r#"{
+                "SOL_Lv": 45,
+                "SOL_RankMatchPlayCount": 812,
+                "SOL_RankMatchWinCount": 430,
+                "KYK_Lv": 3,
+                "MAY_RankMatchRatingPt": 1500
+            }"#,
+        )
+        .unwrap();
+        let now = chrono::Utc::now().naive_utc();
+
+        let stats = parse_official_stats(7, &parsed, now);
+
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].id, 7);
+        assert_eq!(stats[0].ranked_games, Some(812));
+        assert_eq!(stats[0].ranked_wins, Some(430));
+        assert_eq!(stats[1].char_id, 1);
+        assert_eq!(stats[1].level, Some(3));
+        assert_eq!(stats[1].ranked_games, None);
+    }
+
+    #[test]
+    fn change_only_when_different() {
+        assert_eq!(change(3, 3), None);
+        assert_eq!(
+            change("Old".to_string(), "New".to_string()),
+            Some(Change {
+                previous: Some("Old".to_string()),
+                value: "New".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn next_refresh_batch_wraps() {
+        let candidates = [3, 8, 12, 20];
+
+        assert_eq!(next_refresh_batch(&candidates, 0, 2), vec![3, 8]);
+        assert_eq!(next_refresh_batch(&candidates, 8, 3), vec![12, 20, 3]);
+        //The cursor player may have dropped out of the candidates since
+        assert_eq!(next_refresh_batch(&candidates, 10, 2), vec![12, 20]);
+        assert_eq!(next_refresh_batch(&candidates, 20, 10), vec![3, 8, 12, 20]);
+        assert!(next_refresh_batch(&[], 5, 10).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rating_changes_new_and_changed() {
+        characters::set_test_characters();
+
+        let mut current = HashMap::new();
+        current.insert(0, 1500);
+        current.insert(1, 2000);
+
+        let changes = rating_changes(&current, &[(0, 1500), (1, 2100), (2, 900)]);
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].char_short, "KY");
+        assert_eq!(changes[0].previous, Some(2000));
+        assert_eq!(changes[1].char_short, "MA");
+        assert_eq!(changes[1].previous, None);
+    }
+}
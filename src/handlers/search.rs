@@ -1,6 +1,21 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{models::{Player, PlayerRating}, CHAR_NAMES};
+use crate::{characters, models::PlayerRating};
+
+use super::common::TagResponse;
+
+pub const SEARCH_DEFAULT_COUNT: usize = 50;
+pub const SEARCH_MAX_COUNT: usize = 100;
+
+/// Characters listed per player, highest rated first.
+const SEARCH_CHARACTERS: usize = 3;
+
+/// Prefix and contains searches use the trigram indexes, anything shorter would scan every name.
+/// Exact searches have their own index and can be as short as a name.
+pub const SEARCH_MIN_LENGTH: usize = 3;
+pub const SEARCH_MAX_LENGTH: usize = 64;
 
 #[derive(Serialize)]
 pub struct SearchResponse {
@@ -10,6 +25,16 @@ pub struct SearchResponse {
 struct PlayerSearchResponse {
     id: i64,
     name: String,
+    /// The old name that matched, when it isn't the player's current name.
+    matched_name: Option<String>,
+    rating: i64,
+    char_short: String,
+    char_long: String,
+    characters: Vec<SearchCharacterResponse>,
+    tags: Vec<TagResponse>,
+}
+#[derive(Serialize)]
+struct SearchCharacterResponse {
     rating: i64,
     char_short: String,
     char_long: String,
@@ -17,20 +42,251 @@ struct PlayerSearchResponse {
 #[derive(Deserialize)]
 pub struct SearchParams {
     pub search_string: String,
+    /// Same as `mode=exact`, kept for old links.
     pub exact: Option<bool>,
+    pub mode: Option<SearchMode>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// The whole name, ignoring case.
+    Exact,
+    /// Names starting with the search string.
+    Prefix,
+    /// Names containing the search string, or similar to it.
+    Contains,
+}
+
+impl SearchParams {
+    pub fn mode(&self) -> SearchMode {
+        match (self.mode, self.exact) {
+            (Some(mode), _) => mode,
+            (None, Some(true)) => SearchMode::Exact,
+            (None, _) => SearchMode::Contains,
+        }
+    }
+}
+
+/// The trimmed search string, or why it can't be searched for in this mode.
+pub fn validate_search(search_string: &str, mode: SearchMode) -> Result<&str, String> {
+    let search_string = search_string.trim();
+    let length = search_string.chars().count();
+
+    if length == 0 {
+        return Err("Search for a name or id".to_string());
+    }
+    if length < SEARCH_MIN_LENGTH && mode != SearchMode::Exact {
+        return Err(format!(
+            "Search for at least {SEARCH_MIN_LENGTH} characters"
+        ));
+    }
+    if length > SEARCH_MAX_LENGTH {
+        return Err(format!("Search for at most {SEARCH_MAX_LENGTH} characters"));
+    }
+
+    Ok(search_string)
+}
+
+/// The ILIKE pattern for the mode, with `%`, `_` and `\` in the search string matched literally.
+pub fn like_pattern(search_string: &str, mode: SearchMode) -> String {
+    let escaped = search_string
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    match mode {
+        SearchMode::Exact => escaped,
+        SearchMode::Prefix => format!("{escaped}%"),
+        SearchMode::Contains => format!("%{escaped}%"),
+    }
+}
+
+/// A player whose current or old name matched, best match first.
+pub struct SearchMatch {
+    pub id: i64,
+    pub name: String,
+    pub matched_name: String,
+}
+
+/// Searches for a number also find the player with that id.
+pub fn search_id(search_string: &str) -> Option<i64> {
+    search_string.trim().parse().ok()
 }
 
-pub async fn player_search(data: Vec<(Player, PlayerRating)>) -> Result<SearchResponse, String> {
-    let results = data
-        .iter()
-        .map(|p| PlayerSearchResponse {
-            id: p.0.id,
-            name: p.0.name.clone(),
-            rating: p.1.value,
-            char_short: CHAR_NAMES[p.1.char_id as usize].0.to_string(),
-            char_long: CHAR_NAMES[p.1.char_id as usize].1.to_string(),
+pub async fn player_search(
+    matches: Vec<SearchMatch>,
+    ratings: Vec<PlayerRating>,
+    tags: HashMap<i64, Vec<(String, String)>>,
+) -> Result<SearchResponse, String> {
+    let mut player_ratings: HashMap<i64, Vec<PlayerRating>> = HashMap::new();
+    for rating in ratings {
+        player_ratings.entry(rating.id).or_default().push(rating);
+    }
+
+    let results = matches
+        .into_iter()
+        .filter_map(|m| {
+            let mut ratings = player_ratings.remove(&m.id)?;
+            ratings.sort_by_key(|r| std::cmp::Reverse(r.value));
+
+            let characters: Vec<SearchCharacterResponse> = ratings
+                .iter()
+                .take(SEARCH_CHARACTERS)
+                .map(|r| {
+                    let character = characters::get(r.char_id);
+
+                    SearchCharacterResponse {
+                        rating: r.value,
+                        char_short: character.short,
+                        char_long: character.name,
+                    }
+                })
+                .collect();
+
+            let best = &characters[0];
+
+            Some(PlayerSearchResponse {
+                id: m.id,
+                matched_name: (m.matched_name != m.name).then_some(m.matched_name),
+                name: m.name,
+                rating: best.rating,
+                char_short: best.char_short.clone(),
+                char_long: best.char_long.clone(),
+                tags: tags
+                    .get(&m.id)
+                    .map(|tags| {
+                        tags.iter()
+                            .map(|(tag, style)| TagResponse {
+                                tag: tag.clone(),
+                                style: style.clone(),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                characters,
+            })
         })
         .collect();
 
     Ok(SearchResponse { results })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(id: i64, char_id: i16, value: i64) -> PlayerRating {
+        PlayerRating { id, char_id, value }
+    }
+
+    #[tokio::test]
+    async fn player_search_grouped_per_player() {
+        characters::set_test_characters();
+
+        let matches = vec![
+            SearchMatch {
+                id: 2,
+                name: "Current".to_string(),
+                matched_name: "Old".to_string(),
+            },
+            SearchMatch {
+                id: 1,
+                name: "Player".to_string(),
+                matched_name: "Player".to_string(),
+            },
+        ];
+        let ratings = vec![
+            rating(1, 0, 1500),
+            rating(2, 0, 1200),
+            rating(1, 2, 1800),
+            rating(1, 1, 1000),
+        ];
+        let mut tags = HashMap::new();
+        tags.insert(1, vec![("VIP".to_string(), "gold".to_string())]);
+
+        let response = player_search(matches, ratings, tags).await.unwrap();
+
+        assert_eq!(response.results.len(), 2);
+
+        let current = &response.results[0];
+        assert_eq!(current.matched_name.as_deref(), Some("Old"));
+        assert!(current.tags.is_empty());
+
+        let player = &response.results[1];
+        assert_eq!(player.matched_name, None);
+        assert_eq!((player.char_short.as_str(), player.rating), ("MA", 1800));
+        assert_eq!(
+            player
+                .characters
+                .iter()
+                .map(|c| c.rating)
+                .collect::<Vec<_>>(),
+            vec![1800, 1500, 1000]
+        );
+        assert_eq!(player.tags[0].tag, "VIP");
+    }
+
+    #[tokio::test]
+    async fn player_search_skips_unrated() {
+        let matches = vec![SearchMatch {
+            id: 3,
+            name: "Unrated".to_string(),
+            matched_name: "Unrated".to_string(),
+        }];
+
+        let response = player_search(matches, vec![], HashMap::new())
+            .await
+            .unwrap();
+
+        assert!(response.results.is_empty());
+    }
+
+    #[test]
+    fn search_mode_from_params() {
+        let params = |exact, mode| SearchParams {
+            search_string: "Player".to_string(),
+            exact,
+            mode,
+        };
+
+        assert_eq!(params(None, None).mode(), SearchMode::Contains);
+        assert_eq!(params(Some(true), None).mode(), SearchMode::Exact);
+        assert_eq!(
+            params(Some(true), Some(SearchMode::Prefix)).mode(),
+            SearchMode::Prefix
+        );
+    }
+
+    #[test]
+    fn validate_search_length() {
+        assert_eq!(validate_search("  Sol  ", SearchMode::Contains), Ok("Sol"));
+        assert!(validate_search(" a ", SearchMode::Prefix).is_err());
+        //Lengths are in characters, not bytes
+        assert_eq!(
+            validate_search("ソル", SearchMode::Contains),
+            Err("Search for at least 3 characters".to_string())
+        );
+        assert!(validate_search(&"a".repeat(SEARCH_MAX_LENGTH + 1), SearchMode::Exact).is_err());
+    }
+
+    #[test]
+    fn validate_search_exact_short() {
+        assert_eq!(validate_search(" a ", SearchMode::Exact), Ok("a"));
+        assert_eq!(validate_search("ソル", SearchMode::Exact), Ok("ソル"));
+        assert!(validate_search("  ", SearchMode::Exact).is_err());
+    }
+
+    #[test]
+    fn like_pattern_escaped() {
+        assert_eq!(like_pattern("100%_Sol", SearchMode::Exact), "100\\%\\_Sol");
+        assert_eq!(like_pattern("a\\b", SearchMode::Prefix), "a\\\\b%");
+        assert_eq!(like_pattern("Sol", SearchMode::Contains), "%Sol%");
+    }
+
+    #[test]
+    fn search_id_numbers_only() {
+        assert_eq!(search_id(" 230303113 "), Some(230303113));
+        assert_eq!(search_id("Player 2"), None);
+    }
+}
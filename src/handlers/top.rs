@@ -3,8 +3,8 @@ use std::collections::HashMap;
 use serde::Serialize;
 
 use crate::{
+    characters,
     models::{CharacterRank, GlobalRank, Player, PlayerRating},
-    CHAR_NAMES,
 };
 
 use super::common::TagResponse;
@@ -36,8 +36,8 @@ pub async fn get_top(
             id: p.1.id,
             name: p.1.name.clone(),
             rating: p.2.value,
-            char_short: CHAR_NAMES[p.0.char_id as usize].0.to_string(),
-            char_long: CHAR_NAMES[p.0.char_id as usize].1.to_string(),
+            char_short: characters::get(p.0.char_id).short,
+            char_long: characters::get(p.0.char_id).name,
             tags: tags
                 .get(&p.1.id)
                 .unwrap_or(&vec![])
@@ -64,8 +64,8 @@ pub async fn get_top_char(
             id: p.1.id,
             name: p.1.name.clone(),
             rating: p.2.value,
-            char_short: CHAR_NAMES[p.0.char_id as usize].0.to_string(),
-            char_long: CHAR_NAMES[p.0.char_id as usize].1.to_string(),
+            char_short: characters::get(p.0.char_id).short,
+            char_long: characters::get(p.0.char_id).name,
             tags: tags
                 .get(&p.1.id)
                 .unwrap_or(&vec![])
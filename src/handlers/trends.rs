@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    characters,
+    handlers::common::PatchResponse,
+    models::{CharacterDailyStat, Patch},
+};
+
+#[derive(Deserialize)]
+pub struct TrendsParams {
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct TrendsResponse {
+    characters: Vec<CharacterTrend>,
+    patches: Vec<PatchResponse>, //Patches released during the covered days
+}
+
+#[derive(Serialize)]
+struct CharacterTrend {
+    char_short: String,
+    char_name: String,
+    days: Vec<TrendEntry>,
+}
+
+#[derive(Serialize)]
+struct TrendEntry {
+    day: String,
+    games: i64,
+    players: i64,
+    games_share: f64, //Percentage of that day's character picks
+}
+
+pub async fn handle_get_trends(
+    stats: Vec<CharacterDailyStat>,
+    patches: Vec<Patch>,
+    char_id: Option<i16>,
+) -> Result<TrendsResponse, String> {
+    //Shares are relative to every character, so totals are taken before filtering.
+    let mut day_totals = HashMap::new();
+    for stat in &stats {
+        *day_totals.entry(stat.day).or_insert(0) += stat.games;
+    }
+
+    let characters = characters::all()
+        .iter()
+        .filter(|c| char_id.is_none_or(|char_id| char_id == c.id))
+        .map(|c| CharacterTrend {
+            char_short: c.short.clone(),
+            char_name: c.name.clone(),
+            days: stats
+                .iter()
+                .filter(|s| s.char_id == c.id)
+                .map(|s| TrendEntry {
+                    day: s.day.to_string(),
+                    games: s.games,
+                    players: s.players,
+                    games_share: match day_totals.get(&s.day) {
+                        Some(&total) if total > 0 => {
+                            (s.games as f64 * 10000.0 / total as f64).round() / 100.0
+                        }
+                        _ => 0.0,
+                    },
+                })
+                .collect(),
+        })
+        .collect();
+
+    let first_day = stats.iter().map(|s| s.day).min();
+    let patches = patches
+        .into_iter()
+        .filter(|p| first_day.is_some_and(|first_day| p.start_date.date() >= first_day))
+        .map(|p| PatchResponse {
+            version: p.version,
+            label: p.label,
+            start_date: p.start_date.to_string(),
+        })
+        .collect();
+
+    Ok(TrendsResponse {
+        characters,
+        patches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn get_trends_games_share() {
+        characters::set_test_characters();
+
+        let response = handle_get_trends(get_test_trends_data(), vec![], None)
+            .await
+            .unwrap();
+
+        assert_eq!(response.characters.len(), characters::all().len());
+        assert_eq!(response.characters[0].days[0].games_share, 25.0);
+        assert_eq!(response.characters[1].days[0].games_share, 75.0);
+        assert_eq!(response.characters[0].days[1].games_share, 100.0);
+    }
+
+    #[tokio::test]
+    async fn get_trends_single_character() {
+        characters::set_test_characters();
+
+        let response = handle_get_trends(get_test_trends_data(), vec![], Some(1))
+            .await
+            .unwrap();
+
+        assert_eq!(response.characters.len(), 1);
+        assert_eq!(response.characters[0].char_short, "KY");
+        assert_eq!(response.characters[0].days.len(), 1);
+        assert_eq!(response.characters[0].days[0].players, 20);
+    }
+
+    #[tokio::test]
+    async fn get_trends_patches_in_range() {
+        let patches = vec![
+            Patch {
+                version: "0.3.0".to_string(),
+                start_date: chrono::NaiveDate::from_ymd_opt(2023, 12, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap(),
+                label: "Season 3".to_string(),
+            },
+            Patch {
+                version: "0.3.1".to_string(),
+                start_date: chrono::NaiveDate::from_ymd_opt(2024, 1, 2)
+                    .unwrap()
+                    .and_hms_opt(3, 0, 0)
+                    .unwrap(),
+                label: "0.3.1".to_string(),
+            },
+        ];
+
+        let response = handle_get_trends(get_test_trends_data(), patches, None)
+            .await
+            .unwrap();
+
+        assert_eq!(response.patches.len(), 1);
+        assert_eq!(response.patches[0].version, "0.3.1");
+    }
+
+    fn get_test_trends_data() -> Vec<CharacterDailyStat> {
+        let day_one = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let day_two = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+
+        vec![
+            CharacterDailyStat {
+                day: day_one,
+                char_id: 0,
+                games: 10,
+                players: 5,
+            },
+            CharacterDailyStat {
+                day: day_one,
+                char_id: 1,
+                games: 30,
+                players: 20,
+            },
+            CharacterDailyStat {
+                day: day_two,
+                char_id: 0,
+                games: 5,
+                players: 3,
+            },
+        ]
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    characters,
+    db::TrackedRank,
+    models::{Game, Webhook},
+};
+
+/// Deliveries are dropped after this many failed attempts.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Webhooks a single player can register.
+pub const MAX_WEBHOOKS: usize = 10;
+
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    pub target_id: i64,
+    pub url: String,
+    pub on_game: Option<bool>,
+    pub on_rating_high: Option<bool>,
+    pub on_top_100: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    id: i32,
+    target_id: i64,
+    url: String,
+    on_game: bool,
+    on_rating_high: bool,
+    on_top_100: bool,
+    created_at: String,
+}
+
+/// Body POSTed to the webhook url. `content` is what Discord displays.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebhookEvent {
+    pub event: String,
+    pub player_id: i64,
+    pub player_name: String,
+    pub char_short: String,
+    pub rating: i64,
+    pub rank: Option<i32>,
+    pub timestamp: String,
+    pub content: String,
+}
+
+/// An event waiting in the delivery queue.
+#[derive(Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: i32,
+    pub url: String,
+    pub attempts: u32,
+    pub event: WebhookEvent,
+}
+
+pub fn validate_webhook_request(request: &WebhookRequest) -> Result<(), String> {
+    if !request.url.starts_with("https://") && !request.url.starts_with("http://") {
+        return Err("Webhook url must be http or https".to_string());
+    }
+
+    if request.url.len() > 500 {
+        return Err("Webhook url is too long".to_string());
+    }
+
+    match reqwest::Url::parse(&request.url) {
+        Ok(url) if url.host_str().is_some() => {}
+        _ => return Err("Webhook url is invalid".to_string()),
+    }
+
+    if !request.on_game.unwrap_or(false)
+        && !request.on_rating_high.unwrap_or(false)
+        && !request.on_top_100.unwrap_or(false)
+    {
+        return Err("Webhook has no events".to_string());
+    }
+
+    Ok(())
+}
+
+/// Webhooks are only sent to public addresses, anything else would let them reach
+/// services on the server's own network.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                //Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_address(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+pub fn webhook_response(webhook: Webhook) -> WebhookResponse {
+    WebhookResponse {
+        id: webhook.id,
+        target_id: webhook.target_id,
+        url: webhook.url,
+        on_game: webhook.on_game,
+        on_rating_high: webhook.on_rating_high,
+        on_top_100: webhook.on_top_100,
+        created_at: webhook.created_at.to_string(),
+    }
+}
+
+/// Seconds to wait before retrying a delivery that failed `attempts` times, None to give up.
+pub fn retry_delay(attempts: u32) -> Option<i64> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+
+    Some(30 * 4_i64.pow(attempts.saturating_sub(1)))
+}
+
+fn deliveries(webhooks: Vec<&Webhook>, event: WebhookEvent) -> Vec<WebhookDelivery> {
+    webhooks
+        .into_iter()
+        .map(|w| WebhookDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            webhook_id: w.id,
+            url: w.url.clone(),
+            attempts: 0,
+            event: event.clone(),
+        })
+        .collect()
+}
+
+/// Game and rating high events for newly pulled games, oldest game first.
+/// `highs` holds each player's best rating per character before these games;
+/// a player's first game on a character is never a new high.
+pub fn game_events(
+    games: &[Game],
+    webhooks: &[Webhook],
+    highs: &HashMap<(i64, i16), i64>,
+) -> Vec<WebhookDelivery> {
+    let mut highs = highs.clone();
+    let mut result = vec![];
+
+    for game in games {
+        let timestamp = game.real_timestamp.unwrap_or(game.timestamp).to_string();
+
+        //`winner` is 1 or 2 for the side that won
+        for side in [1, 2] {
+            let (id, name, char_id, value, opponent, opponent_char) = if side == 1 {
+                (
+                    game.id_a,
+                    &game.name_a,
+                    game.char_a,
+                    game.value_a,
+                    &game.name_b,
+                    game.char_b,
+                )
+            } else {
+                (
+                    game.id_b,
+                    &game.name_b,
+                    game.char_b,
+                    game.value_b,
+                    &game.name_a,
+                    game.char_a,
+                )
+            };
+            let char_short = characters::get(char_id).short;
+
+            let new_high = match highs.get(&(id, char_id)) {
+                Some(&high) => value > high,
+                None => false,
+            };
+            highs
+                .entry((id, char_id))
+                .and_modify(|high| *high = (*high).max(value))
+                .or_insert(value);
+
+            let watching: Vec<&Webhook> = webhooks.iter().filter(|w| w.target_id == id).collect();
+
+            let on_game: Vec<&Webhook> = watching.iter().filter(|w| w.on_game).copied().collect();
+            if !on_game.is_empty() {
+                result.extend(deliveries(
+                    on_game,
+                    WebhookEvent {
+                        event: "game".to_string(),
+                        player_id: id,
+                        player_name: name.clone(),
+                        char_short: char_short.clone(),
+                        rating: value,
+                        rank: None,
+                        timestamp: timestamp.clone(),
+                        content: format!(
+                            "{} ({}) {} {} ({})",
+                            name,
+                            char_short,
+                            if game.winner == side {
+                                "beat"
+                            } else {
+                                "lost to"
+                            },
+                            opponent,
+                            characters::get(opponent_char).short
+                        ),
+                    },
+                ));
+            }
+
+            let on_rating_high: Vec<&Webhook> = watching
+                .iter()
+                .filter(|w| w.on_rating_high)
+                .copied()
+                .collect();
+            if new_high && !on_rating_high.is_empty() {
+                result.extend(deliveries(
+                    on_rating_high,
+                    WebhookEvent {
+                        event: "rating_high".to_string(),
+                        player_id: id,
+                        player_name: name.clone(),
+                        char_short: char_short.clone(),
+                        rating: value,
+                        rank: None,
+                        timestamp: timestamp.clone(),
+                        content: format!(
+                            "{} reached a new high of {} on {}",
+                            name, value, char_short
+                        ),
+                    },
+                ));
+            }
+        }
+    }
+
+    result
+}
+
+/// Events for watched players that are in the global top 100 now but weren't before.
+pub fn top_100_events(
+    before: &[TrackedRank],
+    after: &[TrackedRank],
+    webhooks: &[Webhook],
+    timestamp: String,
+) -> Vec<WebhookDelivery> {
+    let mut result = vec![];
+
+    for rank in after.iter().filter(|r| r.rank <= 100) {
+        if before.iter().any(|r| r.id == rank.id && r.rank <= 100) {
+            continue;
+        }
+
+        let watching: Vec<&Webhook> = webhooks
+            .iter()
+            .filter(|w| w.target_id == rank.id && w.on_top_100)
+            .collect();
+        if watching.is_empty() {
+            continue;
+        }
+
+        let char_short = characters::get(rank.char_id).short;
+
+        result.extend(deliveries(
+            watching,
+            WebhookEvent {
+                event: "top_100".to_string(),
+                player_id: rank.id,
+                player_name: rank.name.clone(),
+                char_short: char_short.clone(),
+                rating: rank.value,
+                rank: Some(rank.rank),
+                timestamp: timestamp.clone(),
+                content: format!(
+                    "{} entered the top 100 at #{} ({} {})",
+                    rank.name, rank.rank, char_short, rank.value
+                ),
+            },
+        ));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::common::get_test_game;
+
+    #[test]
+    fn validate_webhook_request_url() {
+        let mut request = get_test_webhook_request();
+        assert!(validate_webhook_request(&request).is_ok());
+
+        request.url = "ftp://example.com".to_string();
+        assert!(validate_webhook_request(&request).is_err());
+
+        request.url = "https://".to_string();
+        assert!(validate_webhook_request(&request).is_err());
+    }
+
+    #[test]
+    fn is_public_address_ranges() {
+        let public = |ip: &str| is_public_address(ip.parse().unwrap());
+
+        assert!(public("162.159.135.232"));
+        assert!(public("2606:4700::6810:84e5"));
+
+        assert!(!public("127.0.0.1"));
+        assert!(!public("10.0.0.1"));
+        assert!(!public("172.16.5.4"));
+        assert!(!public("192.168.1.1"));
+        assert!(!public("169.254.169.254"));
+        assert!(!public("100.64.0.1"));
+        assert!(!public("0.0.0.0"));
+        assert!(!public("::1"));
+        assert!(!public("::"));
+        assert!(!public("fd00::1"));
+        assert!(!public("fe80::1"));
+        assert!(!public("::ffff:127.0.0.1"));
+    }
+
+    #[test]
+    fn validate_webhook_request_no_events() {
+        let mut request = get_test_webhook_request();
+        request.on_game = Some(false);
+
+        assert!(validate_webhook_request(&request).is_err());
+    }
+
+    #[test]
+    fn retry_delay_backs_off() {
+        assert_eq!(retry_delay(1), Some(30));
+        assert_eq!(retry_delay(2), Some(120));
+        assert_eq!(retry_delay(MAX_ATTEMPTS), None);
+    }
+
+    #[test]
+    fn game_events_only_watched_players() {
+        let webhooks = vec![get_test_webhook(1, 2, true, false, false)];
+
+        let events = game_events(&[get_test_game(1500)], &webhooks, &HashMap::new());
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.event, "game");
+        assert_eq!(events[0].event.player_id, 2);
+        assert_eq!(events[0].event.rating, 1500);
+    }
+
+    #[test]
+    fn game_events_rating_high() {
+        let webhooks = vec![get_test_webhook(1, 2, false, true, false)];
+
+        let mut highs = HashMap::new();
+        highs.insert((2, 1), 1400);
+
+        let events = game_events(
+            &[
+                get_test_game(1500),
+                get_test_game(1450),
+                get_test_game(1600),
+            ],
+            &webhooks,
+            &highs,
+        );
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.rating, 1500);
+        assert_eq!(events[1].event.rating, 1600);
+    }
+
+    #[test]
+    fn game_events_first_game_is_not_a_high() {
+        let webhooks = vec![get_test_webhook(1, 2, false, true, false)];
+
+        let events = game_events(&[get_test_game(1500)], &webhooks, &HashMap::new());
+
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn top_100_events_new_entries_only() {
+        let webhooks = vec![
+            get_test_webhook(1, 2, false, false, true),
+            get_test_webhook(2, 3, false, false, true),
+        ];
+
+        let before = vec![get_test_rank(2, 150), get_test_rank(3, 50)];
+        let after = vec![get_test_rank(2, 99), get_test_rank(3, 40)];
+
+        let events = top_100_events(&before, &after, &webhooks, "now".to_string());
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.player_id, 2);
+        assert_eq!(events[0].event.rank, Some(99));
+    }
+
+    fn get_test_webhook_request() -> WebhookRequest {
+        WebhookRequest {
+            target_id: 2,
+            url: "https://example.com/hook".to_string(),
+            on_game: Some(true),
+            on_rating_high: None,
+            on_top_100: None,
+        }
+    }
+
+    fn get_test_webhook(
+        id: i32,
+        target_id: i64,
+        on_game: bool,
+        on_rating_high: bool,
+        on_top_100: bool,
+    ) -> Webhook {
+        Webhook {
+            id,
+            owner_id: 1,
+            target_id,
+            url: "https://example.com/hook".to_string(),
+            on_game,
+            on_rating_high,
+            on_top_100,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    fn get_test_rank(id: i64, rank: i32) -> TrackedRank {
+        TrackedRank {
+            id,
+            rank,
+            char_id: 0,
+            name: format!("Player {}", id),
+            value: 20000,
+        }
+    }
+}
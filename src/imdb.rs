@@ -2,9 +2,18 @@ use std::collections::HashMap;
 
 use bb8_redis::redis;
 use chrono::NaiveDateTime;
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
 use tracing::warn;
 
-use crate::{DistributionEntry, CHAR_NAMES};
+use crate::{
+    characters,
+    handlers::avatar::{all_variant_keys, AVATAR_KEEP_SECONDS},
+    handlers::live::LiveGame,
+    handlers::player::PlayerResponse,
+    handlers::rating_sync::{RatingSyncStatus, RATING_SYNC_COOLDOWN, RATING_SYNC_QUEUE_MAX},
+    handlers::webhooks::WebhookDelivery, DistributionEntry,
+};
 
 async fn get_string(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<String, String> {
     match redis::cmd("GET").arg(key).query_async(&mut **redis).await {
@@ -20,6 +29,11 @@ async fn get_int(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<i6
     }
 }
 
+/// Prefix of the keys holding the summaries scoped to a single patch.
+pub fn patch_prefix(version: &str) -> String {
+    format!("patch_{}:", version)
+}
+
 pub struct Stats {
     pub timestamp: String,
     pub total_games: i64,
@@ -33,24 +47,30 @@ pub struct Stats {
     pub one_day_players: i64,
     pub one_hour_players: i64,
 }
-pub async fn get_stats(redis: &mut crate::RedisConnection<'_>) -> Result<Stats, String> {
-    let timestamp = match get_string("last_update_hourly", redis).await {
+/// `prefix` is empty for the global stats, see `patch_prefix`.
+pub async fn get_stats(
+    prefix: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Stats, String> {
+    let key = |k: &str| format!("{}{}", prefix, k);
+
+    let timestamp = match get_string(&key("last_update_hourly"), redis).await {
         Ok(ts) => ts,
         Err(_) => {
             return Err("Stats (last_update_hourly) not found".to_string());
         }
     };
 
-    let total_games = get_int("total_games", redis).await?;
-    let one_month_games = get_int("one_month_games", redis).await?;
-    let one_week_games = get_int("one_week_games", redis).await?;
-    let one_day_games = get_int("one_day_games", redis).await?;
-    let one_hour_games = get_int("one_hour_games", redis).await?;
-    let total_players = get_int("total_players", redis).await?;
-    let one_month_players = get_int("one_month_players", redis).await?;
-    let one_week_players = get_int("one_week_players", redis).await?;
-    let one_day_players = get_int("one_day_players", redis).await?;
-    let one_hour_players = get_int("one_hour_players", redis).await?;
+    let total_games = get_int(&key("total_games"), redis).await?;
+    let one_month_games = get_int(&key("one_month_games"), redis).await?;
+    let one_week_games = get_int(&key("one_week_games"), redis).await?;
+    let one_day_games = get_int(&key("one_day_games"), redis).await?;
+    let one_hour_games = get_int(&key("one_hour_games"), redis).await?;
+    let total_players = get_int(&key("total_players"), redis).await?;
+    let one_month_players = get_int(&key("one_month_players"), redis).await?;
+    let one_week_players = get_int(&key("one_week_players"), redis).await?;
+    let one_day_players = get_int(&key("one_day_players"), redis).await?;
+    let one_hour_players = get_int(&key("one_hour_players"), redis).await?;
 
     Ok(Stats {
         timestamp,
@@ -74,33 +94,43 @@ pub struct Popularity {
     pub per_character_total: i64,
     pub last_update: String,
 }
-pub async fn get_popularity(redis: &mut crate::RedisConnection<'_>) -> Result<Popularity, String> {
-    let mut per_player: Vec<(String, i64)> = vec![];
-
-    for e in CHAR_NAMES.iter() {
-        let key = format!("popularity_per_player_{}", e.0);
-
-        let value: i64 = match get_int(&key, redis).await {
+/// `prefix` is empty for the last month, see `patch_prefix`.
+pub async fn get_popularity(
+    prefix: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Popularity, String> {
+    let per_player_total =
+        match get_int(&format!("{}popularity_per_player_total", prefix), redis).await {
             Ok(v) => v,
             Err(_) => {
                 return Err("Popularity not found".to_string());
             }
         };
 
-        per_player.push((e.1.to_string(), value));
+    //Characters registered since the last update don't have keys yet
+    let mut per_player: Vec<(String, i64)> = vec![];
+
+    for c in characters::all().iter() {
+        let key = format!("{}popularity_per_player_{}", prefix, c.short);
+        let value: i64 = get_int(&key, redis).await.unwrap_or(0);
+        per_player.push((c.name.clone(), value));
     }
 
     let mut per_character: Vec<(String, i64)> = vec![];
 
-    for e in CHAR_NAMES.iter() {
-        let key = format!("popularity_per_character_{}", e.0);
-        let value: i64 = get_int(&key, redis).await?;
-        per_character.push((e.1.to_string(), value));
+    for c in characters::all().iter() {
+        let key = format!("{}popularity_per_character_{}", prefix, c.short);
+        let value: i64 = get_int(&key, redis).await.unwrap_or(0);
+        per_character.push((c.name.clone(), value));
     }
 
-    let per_player_total = get_int("popularity_per_player_total", redis).await?;
-    let per_character_total = get_int("one_month_games", redis).await?;
-    let last_update = get_string("last_update_daily", redis).await?;
+    //Popularity covers the last month globally, and the whole patch otherwise
+    let per_character_total = if prefix.is_empty() {
+        get_int("one_month_games", redis).await?
+    } else {
+        get_int(&format!("{}total_games", prefix), redis).await?
+    };
+    let last_update = get_string(&format!("{}last_update_daily", prefix), redis).await?;
 
     Ok(Popularity {
         per_player,
@@ -129,43 +159,38 @@ async fn get_matchup(
     redis: &mut crate::RedisConnection<'_>,
 ) -> Result<Vec<MatchupChar>, String> {
     let mut matchups = vec![];
+    let registry = characters::all();
 
-    for c in 0..CHAR_NAMES.len() {
-        let key = format!("{}_{}", prefix, c);
+    for c in registry.iter() {
+        let key = format!("{}_{}", prefix, c.id);
 
-        let value: String = match get_string(&key, redis).await {
-            Ok(v) => v,
-            Err(_) => {
-                return Err("Matchup not found".to_string());
-            }
+        //Characters registered since the last update don't have a key yet
+        let matchups_data: Vec<crate::pull::Matchup> = match get_string(&key, redis).await {
+            Ok(v) => serde_json::from_str(&v).unwrap(),
+            Err(_) => vec![],
         };
 
-        let matchups_data: Vec<crate::pull::Matchup> = serde_json::from_str(&value).unwrap();
-        let char_name = CHAR_NAMES[c].1.to_string();
-        let char_short = CHAR_NAMES[c].0.to_string();
-
         let matchup = MatchupChar {
-            char_name,
-            char_short,
-            matchups: CHAR_NAMES
+            char_name: c.name.clone(),
+            char_short: c.short.clone(),
+            matchups: registry
                 .iter()
-                .enumerate()
-                .map(|(i, char_info)| {
-                    // Look for an entry in matchups_data for this character index
+                .map(|char_info| {
+                    // Look for an entry in matchups_data for this character
                     let matchup_entry = matchups_data
                         .iter()
-                        .find(|m| m.opponent_char as usize == i);
+                        .find(|m| m.opponent_char == char_info.id);
                     
                     match matchup_entry {
                         Some(m) => MatchupEntry {
-                            char_name: char_info.1.to_string(),
-                            char_short: char_info.0.to_string(),
+                            char_name: char_info.name.clone(),
+                            char_short: char_info.short.clone(),
                             wins: m.wins,
                             total_games: m.total_games,
                         },
                         None => MatchupEntry {
-                            char_name: char_info.1.to_string(),
-                            char_short: char_info.0.to_string(),
+                            char_name: char_info.name.clone(),
+                            char_short: char_info.short.clone(),
                             wins: 0,
                             total_games: 0,
                         },
@@ -182,16 +207,20 @@ pub struct Matchups {
     pub last_update: String,
     pub matchups: HashMap<String, Vec<MatchupChar>>,
 }
-pub async fn get_matchups(redis: &mut crate::RedisConnection<'_>) -> Result<Matchups, String> {
-    let prefixes = vec!["matchup", "matchup_vanq"];
+/// `prefix` is empty for the last month, see `patch_prefix`.
+pub async fn get_matchups(
+    prefix: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Matchups, String> {
+    let names = vec!["matchup", "matchup_vanq"];
     let mut matchups: HashMap<String, Vec<MatchupChar>> = HashMap::new();
 
-    for prefix in prefixes {
-        let matchup_char = get_matchup(prefix, redis).await?;
-        matchups.insert(prefix.to_string(), matchup_char);
+    for name in names {
+        let matchup_char = get_matchup(&format!("{}{}", prefix, name), redis).await?;
+        matchups.insert(name.to_string(), matchup_char);
     }
 
-    let last_update = get_string("last_update_daily", redis).await?;
+    let last_update = get_string(&format!("{}last_update_daily", prefix), redis).await?;
 
     Ok(Matchups {
         last_update,
@@ -199,17 +228,19 @@ pub async fn get_matchups(redis: &mut crate::RedisConnection<'_>) -> Result<Matc
     })
 }
 
-pub async fn get_distribution(
+async fn get_distribution_from(
+    distribution_key: &str,
+    players_key: &str,
     redis: &mut crate::RedisConnection<'_>,
 ) -> Result<(String, DistributionEntry), String> {
-    let distribution_rating = get_string("distribution_rating", redis).await?;
+    let distribution_rating = get_string(distribution_key, redis).await?;
 
     //Deserialize distribution_rating
     let distribution_rating: Vec<crate::pull::DistributionResult> =
         serde_json::from_str(&distribution_rating).unwrap();
 
-    //Get one_month_players
-    let one_month_players = get_int("one_month_players", redis).await?;
+    //Get the number of players active in the last month
+    let one_month_players = get_int(players_key, redis).await?;
 
     let timestamp = get_string("last_update_daily", redis).await?;
 
@@ -222,6 +253,251 @@ pub async fn get_distribution(
     ))
 }
 
+pub async fn get_distribution(
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(String, DistributionEntry), String> {
+    get_distribution_from("distribution_rating", "one_month_players", redis).await
+}
+
+pub async fn get_char_distribution(
+    char_id: i16,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(String, DistributionEntry), String> {
+    let char_short = characters::get(char_id).short;
+
+    get_distribution_from(
+        &format!("distribution_rating_{}", char_short),
+        &format!("popularity_per_player_{}", char_short),
+        redis,
+    )
+    .await
+}
+
+/// Redis channel the pull process publishes new games on.
+pub const LIVE_GAMES_CHANNEL: &str = "live_games";
+
+pub async fn publish_games(
+    games: &[LiveGame],
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    for game in games {
+        if let Err(e) = redis::cmd("PUBLISH")
+            .arg(LIVE_GAMES_CHANNEL)
+            .arg(serde_json::to_string(game).unwrap())
+            .query_async::<i64>(&mut **redis)
+            .await
+        {
+            return Err(format!("Failed to publish game: {e}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Forwards every game published on `LIVE_GAMES_CHANNEL` to `sender`, reconnecting on errors.
+/// Pub/sub needs a dedicated connection, so this doesn't use the pool.
+pub async fn subscribe_games(redis_url: String, sender: broadcast::Sender<LiveGame>) {
+    loop {
+        if let Err(e) = forward_games(&redis_url, &sender).await {
+            warn!("Live games subscription lost: {e}");
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+}
+
+async fn forward_games(
+    redis_url: &str,
+    sender: &broadcast::Sender<LiveGame>,
+) -> Result<(), redis::RedisError> {
+    let client = redis::Client::open(redis_url)?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(LIVE_GAMES_CHANNEL).await?;
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+
+        match serde_json::from_str::<LiveGame>(&payload) {
+            //Nobody listening isn't an error
+            Ok(game) => {
+                let _ = sender.send(game);
+            }
+            Err(e) => warn!("Invalid live game: {e}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Sorted set of pending webhook deliveries, scored by when to attempt them next.
+const WEBHOOK_QUEUE: &str = "webhook_queue";
+
+pub async fn queue_webhook(
+    delivery: &WebhookDelivery,
+    at: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    match redis::cmd("ZADD")
+        .arg(WEBHOOK_QUEUE)
+        .arg(at)
+        .arg(serde_json::to_string(delivery).unwrap())
+        .query_async::<i64>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to queue webhook".to_string()),
+    }
+}
+
+/// Removes and returns up to `count` deliveries due by `now`.
+/// A delivery only goes to whoever manages to remove it, so concurrent workers don't send it twice.
+pub async fn take_due_webhooks(
+    now: i64,
+    count: usize,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Vec<WebhookDelivery>, String> {
+    let due: Vec<String> = match redis::cmd("ZRANGEBYSCORE")
+        .arg(WEBHOOK_QUEUE)
+        .arg("-inf")
+        .arg(now)
+        .arg("LIMIT")
+        .arg(0)
+        .arg(count)
+        .query_async(&mut **redis)
+        .await
+    {
+        Ok(due) => due,
+        Err(_) => return Err("Failed to read webhook queue".to_string()),
+    };
+
+    let mut deliveries = vec![];
+
+    for entry in due {
+        let removed: i64 = match redis::cmd("ZREM")
+            .arg(WEBHOOK_QUEUE)
+            .arg(&entry)
+            .query_async(&mut **redis)
+            .await
+        {
+            Ok(removed) => removed,
+            Err(_) => return Err("Failed to update webhook queue".to_string()),
+        };
+
+        if removed == 1 {
+            match serde_json::from_str(&entry) {
+                Ok(delivery) => deliveries.push(delivery),
+                Err(e) => warn!("Dropping invalid webhook delivery: {e}"),
+            }
+        }
+    }
+
+    Ok(deliveries)
+}
+
+/// Invalidating a player moves them to a new generation, pages cached under an older one are
+/// never read again and expire on their own. A page rendered from data read before the
+/// invalidation is written under the generation it started with, so it can't come back.
+fn player_generation_key(id: i64) -> String {
+    format!("player_cache_generation:{}", id)
+}
+
+/// Each patch's page is its own key with its own expiry.
+fn player_cache_key(id: i64, generation: &str, patch: Option<&str>) -> String {
+    format!("player_cache:{}:{}:{}", id, generation, patch.unwrap_or("all"))
+}
+
+/// Ranks and percentiles change without the player playing, so entries also expire.
+const PLAYER_CACHE_SECONDS: i64 = 3600;
+
+/// Generations outlive the pages cached under them, with room for a page that was being
+/// rendered when the generation changed.
+const PLAYER_GENERATION_SECONDS: i64 = PLAYER_CACHE_SECONDS + 60;
+
+/// The cached page if there is one, and the generation to cache a freshly rendered one under.
+/// Read the generation before the data the page is rendered from.
+pub async fn get_cached_player(
+    id: i64,
+    patch: Option<&str>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(Option<PlayerResponse>, String), String> {
+    let generation: Option<String> = match redis::cmd("GET")
+        .arg(player_generation_key(id))
+        .query_async(&mut **redis)
+        .await
+    {
+        Ok(generation) => generation,
+        Err(_) => return Err("Failed to read player cache".to_string()),
+    };
+    let generation = generation.unwrap_or_else(|| "0".to_string());
+
+    let cached: Option<String> = match redis::cmd("GET")
+        .arg(player_cache_key(id, &generation, patch))
+        .query_async(&mut **redis)
+        .await
+    {
+        Ok(cached) => cached,
+        Err(_) => return Err("Failed to read player cache".to_string()),
+    };
+
+    match cached.map(|c| serde_json::from_str(&c)) {
+        Some(Ok(player)) => Ok((Some(player), generation)),
+        Some(Err(e)) => {
+            warn!("Ignoring invalid cached player {}: {e}", id);
+            Ok((None, generation))
+        }
+        None => Ok((None, generation)),
+    }
+}
+
+pub async fn set_cached_player(
+    id: i64,
+    generation: &str,
+    patch: Option<&str>,
+    player: &PlayerResponse,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    match redis::cmd("SET")
+        .arg(player_cache_key(id, generation, patch))
+        .arg(serde_json::to_string(player).unwrap())
+        .arg("EX")
+        .arg(PLAYER_CACHE_SECONDS)
+        .query_async::<()>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to set player cache".to_string()),
+    }
+}
+
+/// Drops the cached pages of every player in `ids` by moving them to a new generation.
+pub async fn invalidate_players(
+    ids: &[i64],
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    //Unique per invalidation, a counter would start over once its key expired
+    let generation = uuid::Uuid::new_v4().to_string();
+
+    let mut pipe = redis::pipe();
+    for id in ids {
+        pipe.cmd("SET")
+            .arg(player_generation_key(*id))
+            .arg(&generation)
+            .arg("EX")
+            .arg(PLAYER_GENERATION_SECONDS)
+            .ignore();
+    }
+
+    match pipe.query_async::<()>(&mut **redis).await {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to invalidate player cache".to_string()),
+    }
+}
+
 pub async fn get_latest_game_time(
     redis: &mut crate::RedisConnection<'_>,
 ) -> Result<NaiveDateTime, String> {
@@ -232,10 +508,10 @@ pub async fn get_latest_game_time(
         }
     };
 
-    let latest_game_time =
-        NaiveDateTime::parse_from_str(&latest_game_time, "%Y-%m-%d %H:%M:%S").unwrap();
-
-    Ok(latest_game_time)
+    match NaiveDateTime::parse_from_str(&latest_game_time, "%Y-%m-%d %H:%M:%S") {
+        Ok(latest_game_time) => Ok(latest_game_time),
+        Err(_) => Err("Invalid latest_game_time".to_string()),
+    }
 }
 
 pub async fn set_latest_game_time(
@@ -267,84 +543,378 @@ pub async fn clear_latest_game_time(redis: &mut crate::RedisConnection<'_>) -> R
 pub async fn get_last_update_daily(
     redis: &mut crate::RedisConnection<'_>,
 ) -> Result<NaiveDateTime, String> {
-    let last_update_daily = match get_string("last_update_daily", redis).await {
-        Ok(lud) => lud,
+    get_last_update("last_update_daily", redis).await
+}
+
+pub async fn get_last_update_hourly(
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<NaiveDateTime, String> {
+    get_last_update("last_update_hourly", redis).await
+}
+
+async fn get_last_update(
+    key: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<NaiveDateTime, String> {
+    let last_update = match get_string(key, redis).await {
+        Ok(lu) => lu,
         Err(_) => {
-            return Err("Failed to get last_update_daily".to_string());
+            return Err(format!("Failed to get {}", key));
         }
     };
 
-    let last_update_daily =
-        NaiveDateTime::parse_from_str(&last_update_daily, "%Y-%m-%d %H:%M:%S").unwrap();
+    match NaiveDateTime::parse_from_str(&last_update, "%Y-%m-%d %H:%M:%S") {
+        Ok(last_update) => Ok(last_update),
+        Err(_) => Err(format!("Invalid {}", key)),
+    }
+}
+
+pub async fn ping(redis: &mut crate::RedisConnection<'_>) -> Result<(), String> {
+    match redis::cmd("PING").query_async::<String>(&mut **redis).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn avatar_key(id: i64) -> String {
+    format!("avatar_{}", id)
+}
 
-    Ok(last_update_daily)
+/// A processed variant of the avatar, each with its own expiry.
+fn avatar_variant_key(id: i64, variant: &str) -> String {
+    format!("avatar_variant_{}_{}", id, variant)
 }
 
-pub async fn get_avatar(id: i64, redis: &mut crate::RedisConnection<'_>) -> Result<String, String> {
-    let key = format!("avatar_{}", id);
+/// The game's base64 PNG and the seconds until it expires.
+pub async fn get_avatar(
+    id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<(String, i64)>, String> {
+    let key = avatar_key(id);
 
-    match get_string(&key, redis).await {
-        Ok(avatar) => Ok(avatar),
+    match redis::pipe()
+        .cmd("GET")
+        .arg(&key)
+        .cmd("TTL")
+        .arg(&key)
+        .query_async::<(Option<String>, i64)>(&mut **redis)
+        .await
+    {
+        Ok((avatar, ttl)) => Ok(avatar.map(|avatar| (avatar, ttl))),
         Err(_) => Err("Failed to get avatar".to_string()),
     }
 }
 
+/// Replaces the avatar and drops the variants processed from the old one.
 pub async fn set_avatar(
     id: i64,
     avatar: &str,
     redis: &mut crate::RedisConnection<'_>,
 ) -> Result<(), String> {
-    let key = format!("avatar_{}", id);
+    match redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(avatar_key(id))
+        .arg(avatar)
+        .arg("EX")
+        .arg(AVATAR_KEEP_SECONDS)
+        .ignore()
+        .cmd("DEL")
+        .arg(
+            all_variant_keys()
+                .iter()
+                .map(|variant| avatar_variant_key(id, variant))
+                .collect::<Vec<_>>(),
+        )
+        .ignore()
+        .query_async::<()>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to set avatar".to_string()),
+    }
+}
+
+pub async fn get_avatar_variant(
+    id: i64,
+    variant: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<Vec<u8>>, String> {
+    match redis::cmd("GET")
+        .arg(avatar_variant_key(id, variant))
+        .query_async(&mut **redis)
+        .await
+    {
+        Ok(output) => Ok(output),
+        Err(_) => Err("Failed to get avatar variant".to_string()),
+    }
+}
 
+pub async fn set_avatar_variant(
+    id: i64,
+    variant: &str,
+    output: &[u8],
+    seconds: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
     match redis::cmd("SET")
+        .arg(avatar_variant_key(id, variant))
+        .arg(output)
+        .arg("EX")
+        .arg(seconds)
+        .query_async::<()>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to set avatar variant".to_string()),
+    }
+}
+
+/// Claims the player's sync slot, None if it was free.
+/// Otherwise the seconds until it frees up, checked and set in one step so concurrent requests
+/// can't both claim it.
+pub async fn try_start_rating_sync(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<u64>, String> {
+    let key = format!("rating_sync:{}", player_id);
+
+    match redis::pipe()
+        .atomic()
+        .cmd("SET")
         .arg(&key)
-        .arg(avatar)
+        .arg("1")
+        .arg("NX")
         .arg("EX")
-        .arg("86400")
-        .query_async::<String>(&mut **redis)
+        .arg(RATING_SYNC_COOLDOWN)
+        .cmd("TTL")
+        .arg(&key)
+        .query_async::<(Option<String>, i64)>(&mut **redis)
+        .await
+    {
+        Ok((Some(_), _)) => Ok(None),
+        Ok((None, ttl)) => Ok(Some(ttl.max(1) as u64)),
+        Err(e) => Err(format!("Failed to check rate limit for player {player_id}: {e}")),
+    }
+}
+
+/// Frees a sync slot that was claimed but never used.
+pub async fn release_rating_sync(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    match redis::cmd("DEL")
+        .arg(format!("rating_sync:{}", player_id))
+        .query_async::<()>(&mut **redis)
         .await
     {
         Ok(_) => Ok(()),
-        Err(_) => Err("Failed to set avatar".to_string()),
+        Err(e) => Err(format!("Failed to release rating sync for player {player_id}: {e}")),
+    }
+}
+
+fn rating_sync_status_key(player_id: i64) -> String {
+    format!("rating_sync_status:{}", player_id)
+}
+
+/// Statuses are kept long enough for the player page to see how the sync ended.
+const RATING_SYNC_STATUS_SECONDS: u64 = 60 * 60;
+
+const RATING_SYNC_QUEUE: &str = "rating_sync_queue";
+
+pub async fn set_rating_sync_status(
+    player_id: i64,
+    status: &RatingSyncStatus,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    match redis::cmd("SET")
+        .arg(rating_sync_status_key(player_id))
+        .arg(serde_json::to_string(status).unwrap())
+        .arg("EX")
+        .arg(RATING_SYNC_STATUS_SECONDS)
+        .query_async::<()>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to set rating sync status".to_string()),
     }
 }
 
-pub async fn check_rating_sync_rate_limit(
+pub async fn get_rating_sync_status(
     player_id: i64,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<bool, String> {
-    let rate_limit_key = format!("rating_sync:{}", player_id);
+) -> Result<Option<RatingSyncStatus>, String> {
+    let status: Option<String> = match redis::cmd("GET")
+        .arg(rating_sync_status_key(player_id))
+        .query_async(&mut **redis)
+        .await
+    {
+        Ok(status) => status,
+        Err(_) => return Err("Failed to get rating sync status".to_string()),
+    };
+
+    match status.map(|s| serde_json::from_str(&s)) {
+        Some(Ok(status)) => Ok(Some(status)),
+        Some(Err(e)) => Err(format!("Invalid rating sync status for {player_id}: {e}")),
+        None => Ok(None),
+    }
+}
 
-    match redis::cmd("EXISTS")
-        .arg(&rate_limit_key)
-        .query_async::<i32>(&mut **redis)
+/// Sets the status and adds the player to the queue, unless they're queued already.
+/// Refuses new players once the queue is full.
+const QUEUE_RATING_SYNC_SCRIPT: &str = r#"
+if redis.call('LPOS', KEYS[1], ARGV[1]) then
+    return 1
+end
+if redis.call('LLEN', KEYS[1]) >= tonumber(ARGV[2]) then
+    return 0
+end
+
+redis.call('SET', KEYS[2], ARGV[3], 'EX', ARGV[4])
+redis.call('LPUSH', KEYS[1], ARGV[1])
+
+return 1
+"#;
+
+/// Marks the player as queued and adds them to the queue the pull process works through.
+/// False if the queue is full.
+pub async fn queue_rating_sync(
+    player_id: i64,
+    status: &RatingSyncStatus,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<bool, String> {
+    match redis::cmd("EVAL")
+        .arg(QUEUE_RATING_SYNC_SCRIPT)
+        .arg(2)
+        .arg(RATING_SYNC_QUEUE)
+        .arg(rating_sync_status_key(player_id))
+        .arg(player_id)
+        .arg(RATING_SYNC_QUEUE_MAX)
+        .arg(serde_json::to_string(status).unwrap())
+        .arg(RATING_SYNC_STATUS_SECONDS)
+        .query_async::<i64>(&mut **redis)
         .await
     {
-        Ok(exists) => Ok(exists == 1),
-        Err(_) => {
-            warn!("Failed to check rate limit for player {}", player_id);
-            Ok(false) // Allow on Redis error
-        }
+        Ok(queued) => Ok(queued == 1),
+        Err(_) => Err("Failed to queue rating sync".to_string()),
     }
 }
 
-pub async fn set_rating_sync_rate_limit(
+/// Puts a player taken off the queue back at its front, to be popped next.
+pub async fn requeue_rating_sync(
     player_id: i64,
     redis: &mut crate::RedisConnection<'_>,
 ) -> Result<(), String> {
-    let rate_limit_key = format!("rating_sync:{}", player_id);
+    match redis::cmd("RPUSH")
+        .arg(RATING_SYNC_QUEUE)
+        .arg(player_id)
+        .query_async::<()>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Failed to requeue rating sync".to_string()),
+    }
+}
 
-    match redis::cmd("SETEX")
-        .arg(&rate_limit_key)
-        .arg(60)
-        .arg("1")
+/// The player that has been waiting the longest.
+pub async fn pop_rating_sync(redis: &mut crate::RedisConnection<'_>) -> Result<Option<i64>, String> {
+    match redis::cmd("RPOP")
+        .arg(RATING_SYNC_QUEUE)
+        .query_async(&mut **redis)
+        .await
+    {
+        Ok(player_id) => Ok(player_id),
+        Err(_) => Err("Failed to pop rating sync queue".to_string()),
+    }
+}
+
+const RATING_REFRESH_CURSOR: &str = "rating_refresh_cursor";
+
+/// The last player the scheduled refresh got to, 0 before the first run.
+pub async fn get_rating_refresh_cursor(redis: &mut crate::RedisConnection<'_>) -> Result<i64, String> {
+    match redis::cmd("GET")
+        .arg(RATING_REFRESH_CURSOR)
+        .query_async::<Option<i64>>(&mut **redis)
+        .await
+    {
+        Ok(cursor) => Ok(cursor.unwrap_or(0)),
+        Err(_) => Err("Failed to get rating refresh cursor".to_string()),
+    }
+}
+
+pub async fn set_rating_refresh_cursor(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    match redis::cmd("SET")
+        .arg(RATING_REFRESH_CURSOR)
+        .arg(player_id)
         .query_async::<()>(&mut **redis)
         .await
     {
         Ok(_) => Ok(()),
-        Err(_) => {
-            warn!("Failed to set rate limit for player {}", player_id);
-            Err("Failed to set rate limit".to_string())
-        }
+        Err(_) => Err("Failed to set rating refresh cursor".to_string()),
+    }
+}
+
+/// Refills the bucket for the time since the last request, then takes `ARGV[4]` tokens if there
+/// are enough. Returns whether the request is allowed and how many seconds until there would be.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local refill_per_ms = tonumber(ARGV[2]) / 60000
+local now = tonumber(ARGV[3])
+local count = tonumber(ARGV[4])
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(bucket[1]) or capacity
+local updated = tonumber(bucket[2]) or now
+
+tokens = math.min(capacity, tokens + math.max(0, now - updated) * refill_per_ms)
+
+local allowed = 0
+if tokens >= count then
+    tokens = tokens - count
+    allowed = 1
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / refill_per_ms))
+
+return {allowed, math.ceil((count - tokens) / refill_per_ms / 1000)}
+"#;
+
+/// None if the request is allowed, otherwise the seconds to wait before retrying.
+/// Runs as a script so concurrent requests can't take the same token.
+pub async fn take_rate_limit_token(
+    key: &str,
+    capacity: u32,
+    refill_per_minute: u32,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<u64>, String> {
+    take_rate_limit_tokens(key, capacity, refill_per_minute, 1, redis).await
+}
+
+/// Like `take_rate_limit_token`, for work that costs `count` requests. Takes all of them or none.
+pub async fn take_rate_limit_tokens(
+    key: &str,
+    capacity: u32,
+    refill_per_minute: u32,
+    count: u32,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<u64>, String> {
+    match redis::cmd("EVAL")
+        .arg(TOKEN_BUCKET_SCRIPT)
+        .arg(1)
+        .arg(format!("rate_limit:{key}"))
+        .arg(capacity)
+        .arg(refill_per_minute)
+        .arg(chrono::Utc::now().timestamp_millis())
+        .arg(count)
+        .query_async::<(i64, i64)>(&mut **redis)
+        .await
+    {
+        Ok((1, _)) => Ok(None),
+        Ok((_, retry_after)) => Ok(Some(retry_after.max(1) as u64)),
+        Err(e) => Err(format!("Failed to check rate limit {key}: {e}")),
     }
 }
@@ -1,16 +1,27 @@
 use axum::extract::{Path, Query};
 use axum::http::header::{self, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ORIGIN};
 use axum::http::{HeaderMap, HeaderValue, Method};
-use axum::response::IntoResponse;
-use axum::{extract::State, http::StatusCode, response::Json, routing::get, Router};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get},
+    Router,
+};
 use bb8::PooledConnection;
+use clap::Parser;
 use diesel_async::{pooled_connection::AsyncDieselConnectionManager, AsyncPgConnection};
-use handlers::common::{Pagination, TagResponse};
+use handlers::common::{Pagination, PatchParams, PatchResponse, TagResponse};
 use models::{CharacterRank, GlobalRank, Player};
 use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-use std::ops::Deref;
+use std::convert::Infallible;
+use std::process::ExitCode;
 use std::vec;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
 use tower_http::cors::{Any, CorsLayer};
 use tracing::warn;
 use tracing_appender::non_blocking::WorkerGuard;
@@ -31,64 +42,83 @@ type RedisConnection<'a> = PooledConnection<'a, RedisConnectionManager>;
 struct AppState {
     db_pool: Pool,
     redis_pool: RedisPool,
+    live_games: tokio::sync::broadcast::Sender<handlers::live::LiveGame>,
 }
 
+mod characters;
+mod cli;
+mod config;
 mod db;
 mod ggst_api;
 mod handlers;
 mod imdb;
+mod migrations;
 mod models;
+mod monitoring;
 mod pull;
+mod rate_limit;
 mod requests;
 mod responses;
 mod schema;
+mod webhooks;
+
+/// Games covered by the `patch` query parameter, every game if it's missing.
+async fn patch_scope(
+    params: &PatchParams,
+    db: &mut Connection<'_>,
+) -> Result<db::GameScope, (StatusCode, String)> {
+    let version = match &params.patch {
+        Some(version) => version,
+        None => return Ok(db::GameScope::all()),
+    };
+
+    let patches = match db::get_patches(db).await {
+        Ok(patches) => patches,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
 
-pub const CHAR_NAMES: &[(&str, &str)] = &[
-    ("SO", "Sol"),
-    ("KY", "Ky"),
-    ("MA", "May"),
-    ("AX", "Axl"),
-    ("CH", "Chipp"),
-    ("PO", "Potemkin"),
-    ("FA", "Faust"),
-    ("MI", "Millia"),
-    ("ZA", "Zato-1"),
-    ("RA", "Ramlethal"),
-    ("LE", "Leo"),
-    ("NA", "Nagoriyuki"),
-    ("GI", "Giovanna"),
-    ("AN", "Anji"),
-    ("IN", "I-No"),
-    ("GO", "Goldlewis"),
-    ("JC", "Jack-O'"),
-    ("HA", "Happy Chaos"),
-    ("BA", "Baiken"),
-    ("TE", "Testament"),
-    ("BI", "Bridget"),
-    ("SI", "Sin"),
-    ("BE", "Bedman?"),
-    ("AS", "Asuka"),
-    ("JN", "Johnny"),
-    ("EL", "Elphelt"),
-    ("AB", "A.B.A."),
-    ("SL", "Slayer"),
-    ("DI", "Dizzy"),
-    ("VE", "Venom"),
-    ("UN", "Unika"),
-    ("LU", "Lucy"),
-];
+    if !patches.iter().any(|p| p.version == *version) {
+        return Err((StatusCode::NOT_FOUND, "Patch not found".to_string()));
+    }
+
+    Ok(db::GameScope::patch(version))
+}
 
 async fn player(
     State(pools): State<AppState>,
     Path(id): Path<i64>,
+    Query(patch): Query<PatchParams>,
 ) -> Result<Json<crate::handlers::player::PlayerResponse>, (StatusCode, String)> {
+    let mut redis = pools.redis_pool.get().await.unwrap();
+
+    //Cache misses and errors both fall through to the database, errors without caching the result
+    let generation = match imdb::get_cached_player(id, patch.patch.as_deref(), &mut redis).await {
+        Ok((Some(response), _)) => return Ok(Json(response)),
+        Ok((None, generation)) => Some(generation),
+        Err(e) => {
+            warn!("{e}");
+            None
+        }
+    };
+
     let mut db = pools.db_pool.get().await.unwrap();
 
-    let (player_char, match_counts, top_chars, top_defeated, top_rating, top_global, tags) =
-        match db::get_player_response_data(id, &mut db).await {
-            Ok(response) => response,
-            Err(e) => return Err((StatusCode::NOT_FOUND, e)),
-        };
+    let range = patch_scope(&patch, &mut db).await?;
+
+    let (
+        player_char,
+        match_counts,
+        top_chars,
+        top_defeated,
+        top_rating,
+        percentiles,
+        top_global,
+        tags,
+        official_stats,
+    ) = match db::get_player_response_data(id, range, &mut db).await {
+        Ok(response) => response,
+        Err(e) => return Err((StatusCode::NOT_FOUND, e)),
+    };
 
     match handlers::player::handle_get_player(
         player_char,
@@ -96,12 +126,28 @@ async fn player(
         top_chars,
         top_defeated,
         top_rating,
+        percentiles,
         top_global,
         tags,
+        official_stats,
     )
     .await
     {
-        Ok(response) => Ok(Json(response)),
+        Ok(response) => {
+            if let Some(generation) = generation
+                && let Err(e) = imdb::set_cached_player(
+                    id,
+                    &generation,
+                    patch.patch.as_deref(),
+                    &response,
+                    &mut redis,
+                )
+                .await
+            {
+                warn!("{e}");
+            }
+            Ok(Json(response))
+        }
         Err(e) => Err((StatusCode::NOT_FOUND, e)),
     }
 }
@@ -110,9 +156,10 @@ async fn player_history(
     State(pools): State<AppState>,
     Path((player_id, char_id)): Path<(i64, String)>,
     Query(pagination): Query<Pagination>,
+    Query(patch): Query<PatchParams>,
 ) -> Result<Json<handlers::player_history::PlayerGamesResponse>, (StatusCode, String)> {
-    let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
-        Some(id) => id as i16,
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
         None => {
             return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
         }
@@ -120,11 +167,13 @@ async fn player_history(
 
     let mut db = pools.db_pool.get().await.unwrap();
 
+    let range = patch_scope(&patch, &mut db).await?;
+
     let count = pagination.count.unwrap_or(100) as i64;
     let offset = pagination.offset.unwrap_or(0) as i64;
 
     let games: Vec<models::Game> =
-        match db::get_games(player_id, char_id, count, offset, &mut db).await {
+        match db::get_games(player_id, char_id, count, offset, range, &mut db).await {
             Ok(games) => games,
             Err(e) => return Err((StatusCode::NOT_FOUND, e)),
         };
@@ -187,8 +236,8 @@ async fn top_char(
     let count = pagination.count.unwrap_or(100) as i64;
     let offset = pagination.offset.unwrap_or(0) as i64;
 
-    let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
-        Some(id) => id as i16,
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
         None => {
             return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
         }
@@ -216,33 +265,63 @@ async fn top_char(
     }
 }
 
-async fn characters() -> Result<Json<Vec<(&'static str, &'static str)>>, (StatusCode, String)> {
-    Ok(Json(CHAR_NAMES.to_vec()))
+async fn characters() -> Result<Json<Vec<(String, String)>>, (StatusCode, String)> {
+    Ok(Json(
+        characters::all()
+            .iter()
+            .map(|c| (c.short.clone(), c.name.clone()))
+            .collect(),
+    ))
 }
 
 async fn player_search(
     State(pools): State<AppState>,
     Query(search_params): Query<crate::handlers::search::SearchParams>,
+    Query(pagination): Query<Pagination>,
 ) -> Result<Json<crate::handlers::search::SearchResponse>, (StatusCode, String)> {
+    let mode = search_params.mode();
+    let search_string = match handlers::search::validate_search(&search_params.search_string, mode)
+    {
+        Ok(search_string) => search_string,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
+    };
+
     let mut db = pools.db_pool.get().await.unwrap();
 
-    let data: Vec<(Player, PlayerRating)> = match db::find_player(search_params, &mut db).await {
-        Ok(data) => data,
+    let count = pagination
+        .count
+        .unwrap_or(handlers::search::SEARCH_DEFAULT_COUNT)
+        .min(handlers::search::SEARCH_MAX_COUNT) as i64;
+    let offset = pagination.offset.unwrap_or(0) as i64;
+
+    let matches = match db::find_player(search_string, mode, count, offset, &mut db).await {
+        Ok(matches) => matches,
+        Err(e) => return Err((StatusCode::NOT_FOUND, e)),
+    };
+
+    let ids: Vec<i64> = matches.iter().map(|m| m.id).collect();
+
+    let ratings = match db::get_ratings_for_players(&ids, &mut db).await {
+        Ok(ratings) => ratings,
         Err(e) => return Err((StatusCode::NOT_FOUND, e)),
     };
 
-    //TODO tags
+    //Results are still useful without tags
+    let player_tags = db::get_tags_from_player_list(ids.into_iter().collect(), &mut db)
+        .await
+        .unwrap_or_default();
 
-    match handlers::search::player_search(data).await {
+    match handlers::search::player_search(matches, ratings, player_tags).await {
         Ok(response) => Ok(Json(response)),
         Err(e) => Err((StatusCode::NOT_FOUND, e)),
     }
 }
 
-async fn rating_sync (
+/// Queues a sync of the player's ratings from their in-game stats, done by the pull process.
+async fn rating_sync(
     State(pools): State<AppState>,
     Path(player_id): Path<i64>,
-) -> Result<Json<String>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     if !std::fs::exists("token.txt").unwrap_or(false) {
         return Err((
             StatusCode::NOT_FOUND,
@@ -250,50 +329,48 @@ async fn rating_sync (
         ));
     }
 
-    let mut redis = pools.redis_pool.get().await.unwrap();
+    let mut db = pools.db_pool.get().await.unwrap();
 
-    // Check if player has synced in the last 60 seconds
-    match imdb::check_rating_sync_rate_limit(player_id, &mut redis).await {
-        Ok(true) => {
-            return Err((
-                StatusCode::TOO_MANY_REQUESTS,
-                "Rating sync is limited to once per minute".to_string(),
-            ));
-        },
-        Ok(false) => {}, // Not rate limited, proceed
-        Err(_) => {
-            // Allow on error (logged in imdb function)
-        }
+    match db::player_exists(&mut db, player_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err((StatusCode::NOT_FOUND, "Player not found".to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
 
-    // Set rate limit key with 60 second expiry
-    let _ = imdb::set_rating_sync_rate_limit(player_id, &mut redis).await;
-
-    let mut db = pools.db_pool.get().await.unwrap();
+    let mut redis = pools.redis_pool.get().await.unwrap();
 
-    let json_response = match ggst_api::get_player_stats(player_id.to_string()).await {
-        Ok(json) => json,
-        Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to get player stats: {}", e),
-            ));
+    match imdb::try_start_rating_sync(player_id, &mut redis).await {
+        Ok(None) => {}
+        Ok(Some(retry_after)) => {
+            return Ok(handlers::rate_limit::too_many_requests(retry_after));
         }
-    };
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
 
-    match handlers::rating_sync::parse_player_stats_and_update_ratings(player_id, &json_response, &mut db).await {
-        Ok(updated_ratings) => {
-            if updated_ratings.is_empty() {
-                Ok(Json("No ratings to update".to_string()))
-            } else {
-                let count = updated_ratings.len();
-                Ok(Json(format!("Updated {} character ratings", count)))
-            }
-        },
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to update ratings: {}", e),
+    let status = handlers::rating_sync::rating_sync_status(
+        handlers::rating_sync::QUEUED,
+        "Waiting to sync",
+    );
+    match imdb::queue_rating_sync(player_id, &status, &mut redis).await {
+        Ok(true) => Ok((StatusCode::ACCEPTED, Json(status)).into_response()),
+        Ok(false) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many syncs waiting, try again later".to_string(),
         )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn rating_sync_status(
+    State(pools): State<AppState>,
+    Path(player_id): Path<i64>,
+) -> Result<Json<handlers::rating_sync::RatingSyncStatus>, (StatusCode, String)> {
+    let mut redis = pools.redis_pool.get().await.unwrap();
+
+    match imdb::get_rating_sync_status(player_id, &mut redis).await {
+        Ok(Some(status)) => Ok(Json(status)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "No rating sync queued".to_string())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
 }
 
@@ -321,6 +398,93 @@ async fn settings(
     }))
 }
 
+async fn webhooks_list(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<Vec<handlers::webhooks::WebhookResponse>>, (StatusCode, String)> {
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    let (owner_id, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(_) => {
+            return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
+        }
+    };
+
+    match db::get_webhooks(owner_id, &mut db).await {
+        Ok(webhooks) => Ok(Json(
+            webhooks
+                .into_iter()
+                .map(handlers::webhooks::webhook_response)
+                .collect(),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn webhook_create(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+    Json(request): Json<handlers::webhooks::WebhookRequest>,
+) -> Result<Json<handlers::webhooks::WebhookResponse>, (StatusCode, String)> {
+    if let Err(e) = handlers::webhooks::validate_webhook_request(&request) {
+        return Err((StatusCode::BAD_REQUEST, e));
+    }
+
+    if let Err(e) = webhooks::check_webhook_url(&request.url).await {
+        return Err((StatusCode::BAD_REQUEST, e));
+    }
+
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    let (owner_id, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(_) => {
+            return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
+        }
+    };
+
+    match db::player_exists(&mut db, request.target_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err((StatusCode::NOT_FOUND, "Target player not found".to_string()));
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+
+    match db::get_webhooks(owner_id, &mut db).await {
+        Ok(webhooks) if webhooks.len() >= handlers::webhooks::MAX_WEBHOOKS => {
+            return Err((StatusCode::BAD_REQUEST, "Too many webhooks".to_string()));
+        }
+        Ok(_) => {}
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+
+    match db::create_webhook(owner_id, &request, &mut db).await {
+        Ok(webhook) => Ok(Json(handlers::webhooks::webhook_response(webhook))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn webhook_delete(
+    State(pools): State<AppState>,
+    Path((key, webhook_id)): Path<(String, i32)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    let (owner_id, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(_) => {
+            return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
+        }
+    };
+
+    match db::delete_webhook(owner_id, webhook_id, &mut db).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err((StatusCode::NOT_FOUND, e)),
+    }
+}
+
 async fn alias(
     State(pools): State<AppState>,
     Path(player_id): Path<i64>,
@@ -346,8 +510,8 @@ async fn ratings(
     State(pools): State<AppState>,
     Path((player_id, char_id, duration)): Path<(i64, String, i32)>,
 ) -> Result<Json<Vec<RatingsResponse>>, (StatusCode, String)> {
-    let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
-        Some(id) => id as i16,
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
         None => {
             return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
         }
@@ -376,9 +540,10 @@ async fn ratings(
 async fn player_matchups(
     State(pools): State<AppState>,
     Path((player_id, char_id, duration)): Path<(i64, String, i32)>,
+    Query(patch): Query<PatchParams>,
 ) -> Result<Json<MatchupCharResponse>, (StatusCode, String)> {
-    let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
-        Some(id) => id as i16,
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
         None => {
             return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
         }
@@ -386,7 +551,9 @@ async fn player_matchups(
 
     let mut db = pools.db_pool.get().await.unwrap();
 
-    let char_matchup = match db::get_matchups(player_id, char_id, duration, &mut db).await {
+    let range = patch_scope(&patch, &mut db).await?;
+
+    let char_matchup = match db::get_matchups(player_id, char_id, duration, range, &mut db).await {
         Ok(char_matchup) => char_matchup,
         Err(e) => {
             return Err((StatusCode::NOT_FOUND, e));
@@ -394,13 +561,13 @@ async fn player_matchups(
     };
 
     Ok(Json(MatchupCharResponse {
-        char_short: CHAR_NAMES[char_id as usize].1.to_string(),
-        char_name: CHAR_NAMES[char_id as usize].0.to_string(),
+        char_short: characters::get(char_id).name.to_string(),
+        char_name: characters::get(char_id).short.to_string(),
         matchups: char_matchup
             .iter()
             .map(|m| MatchupEntry {
-                char_name: CHAR_NAMES[m.opponent_char as usize].1.to_string(),
-                char_short: CHAR_NAMES[m.opponent_char as usize].0.to_string(),
+                char_name: characters::get(m.opponent_char).name.to_string(),
+                char_short: characters::get(m.opponent_char).short.to_string(),
                 wins: m.wins,
                 total_games: m.total_games,
             })
@@ -422,10 +589,16 @@ struct StatsResponse {
     one_day_players: i64,
     one_hour_players: i64,
 }
-async fn stats(State(pools): State<AppState>) -> Result<Json<StatsResponse>, (StatusCode, String)> {
+async fn stats(
+    State(pools): State<AppState>,
+    Query(patch): Query<PatchParams>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
     let mut redis = pools.redis_pool.get().await.unwrap();
 
-    let stats = match imdb::get_stats(&mut redis).await {
+    let prefix = patch.patch.map(|v| imdb::patch_prefix(&v)).unwrap_or_default();
+
+    let stats = match imdb::get_stats(&prefix, &mut redis).await {
         Ok(stats) => stats,
         Err(e) => {
             return Err((StatusCode::NOT_FOUND, e));
@@ -446,7 +619,9 @@ async fn stats(State(pools): State<AppState>) -> Result<Json<StatsResponse>, (St
         one_hour_players,
     } = stats;
 
-    Ok(Json(StatsResponse {
+    let validators = handlers::caching::timestamp_validators(&format!("stats {}", prefix), &timestamp);
+
+    let response = Json(StatsResponse {
         timestamp,
         total_games,
         one_month_games,
@@ -458,7 +633,14 @@ async fn stats(State(pools): State<AppState>) -> Result<Json<StatsResponse>, (St
         one_week_players,
         one_day_players,
         one_hour_players,
-    }))
+    });
+
+    Ok(handlers::caching::cached_response(
+        &headers,
+        validators,
+        handlers::caching::HOURLY_MAX_AGE,
+        response,
+    ))
 }
 
 #[derive(Serialize)]
@@ -476,17 +658,26 @@ struct PopularityResult {
 }
 async fn popularity(
     State(pools): State<AppState>,
-) -> Result<Json<PopularityResult>, (StatusCode, String)> {
+    Query(patch): Query<PatchParams>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
     let mut redis = pools.redis_pool.get().await.unwrap();
 
-    let results = match imdb::get_popularity(&mut redis).await {
+    let prefix = patch.patch.map(|v| imdb::patch_prefix(&v)).unwrap_or_default();
+
+    let results = match imdb::get_popularity(&prefix, &mut redis).await {
         Ok(results) => results,
         Err(e) => {
             return Err((StatusCode::NOT_FOUND, e));
         }
     };
 
-    Ok(Json(PopularityResult {
+    let validators = handlers::caching::timestamp_validators(
+        &format!("popularity {}", prefix),
+        &results.last_update,
+    );
+
+    let response = Json(PopularityResult {
         per_player: results
             .per_player
             .iter()
@@ -506,7 +697,65 @@ async fn popularity(
         per_player_total: results.per_player_total,
         per_character_total: results.per_character_total,
         last_update: results.last_update,
-    }))
+    });
+
+    Ok(handlers::caching::cached_response(
+        &headers,
+        validators,
+        handlers::caching::DAILY_MAX_AGE,
+        response,
+    ))
+}
+
+async fn trends(
+    State(pools): State<AppState>,
+    Query(params): Query<handlers::trends::TrendsParams>,
+) -> Result<Json<handlers::trends::TrendsResponse>, (StatusCode, String)> {
+    character_trends(&pools, None, params).await
+}
+
+async fn trends_char(
+    State(pools): State<AppState>,
+    Path(char_id): Path<String>,
+    Query(params): Query<handlers::trends::TrendsParams>,
+) -> Result<Json<handlers::trends::TrendsResponse>, (StatusCode, String)> {
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
+        None => {
+            return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+        }
+    };
+
+    character_trends(&pools, Some(char_id), params).await
+}
+
+async fn character_trends(
+    pools: &AppState,
+    char_id: Option<i16>,
+    params: handlers::trends::TrendsParams,
+) -> Result<Json<handlers::trends::TrendsResponse>, (StatusCode, String)> {
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    let days = params.days.unwrap_or(90).clamp(1, 730);
+
+    let stats = match db::get_character_daily_stats(days, &mut db).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            return Err((StatusCode::NOT_FOUND, e));
+        }
+    };
+
+    let patches = match db::get_patches(&mut db).await {
+        Ok(patches) => patches,
+        Err(e) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, e));
+        }
+    };
+
+    match handlers::trends::handle_get_trends(stats, patches, char_id).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err((StatusCode::NOT_FOUND, e)),
+    }
 }
 
 #[derive(Serialize)]
@@ -532,10 +781,14 @@ struct MatchupEntry {
 
 async fn matchups(
     State(pools): State<AppState>,
-) -> Result<Json<MatchupResponse>, (StatusCode, String)> {
+    Query(patch): Query<PatchParams>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
     let mut redis = pools.redis_pool.get().await.unwrap();
 
-    let matchups = match imdb::get_matchups(&mut redis).await {
+    let prefix = patch.patch.map(|v| imdb::patch_prefix(&v)).unwrap_or_default();
+
+    let matchups = match imdb::get_matchups(&prefix, &mut redis).await {
         Ok(matchups) => matchups,
         Err(e) => {
             return Err((StatusCode::NOT_FOUND, e));
@@ -558,6 +811,9 @@ async fn matchups(
 
     let last_update = matchups.last_update;
 
+    let validators =
+        handlers::caching::timestamp_validators(&format!("matchups {}", prefix), &last_update);
+
     let data_all: Vec<MatchupCharResponse> = data_all
         .iter()
         .map(|m| MatchupCharResponse {
@@ -594,11 +850,16 @@ async fn matchups(
         })
         .collect();
 
-    Ok(Json(MatchupResponse {
-        last_update,
-        data_all,
-        data_vanq,
-    }))
+    Ok(handlers::caching::cached_response(
+        &headers,
+        validators,
+        handlers::caching::DAILY_MAX_AGE,
+        Json(MatchupResponse {
+            last_update,
+            data_all,
+            data_vanq,
+        }),
+    ))
 }
 
 #[derive(Serialize)]
@@ -607,6 +868,30 @@ struct Supporter {
     name: String,
     tags: Vec<TagResponse>,
 }
+async fn patches(
+    State(pools): State<AppState>,
+) -> Result<Json<Vec<PatchResponse>>, (StatusCode, String)> {
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    let patches = match db::get_patches(&mut db).await {
+        Ok(patches) => patches,
+        Err(e) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, e));
+        }
+    };
+
+    Ok(Json(
+        patches
+            .into_iter()
+            .map(|p| PatchResponse {
+                version: p.version,
+                label: p.label,
+                start_date: p.start_date.to_string(),
+            })
+            .collect(),
+    ))
+}
+
 async fn supporters(
     State(pools): State<AppState>,
 ) -> Result<Json<Vec<Supporter>>, (StatusCode, String)> {
@@ -661,7 +946,8 @@ struct DistributionEntry {
 }
 async fn distribution(
     State(pools): State<AppState>,
-) -> Result<Json<DistributionResponse>, (StatusCode, String)> {
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
     let mut redis = pools.redis_pool.get().await.unwrap();
 
     let (ts, distrubition_entry) = match imdb::get_distribution(&mut redis).await {
@@ -671,56 +957,281 @@ async fn distribution(
         }
     };
 
-    Ok(Json(DistributionResponse {
-        timestamp: ts,
-        data: distrubition_entry,
-    }))
+    let validators = handlers::caching::timestamp_validators("distribution", &ts);
+
+    Ok(handlers::caching::cached_response(
+        &headers,
+        validators,
+        handlers::caching::DAILY_MAX_AGE,
+        Json(DistributionResponse {
+            timestamp: ts,
+            data: distrubition_entry,
+        }),
+    ))
 }
 
-async fn health(State(pools): State<AppState>) -> Result<String, (StatusCode, String)> {
+async fn char_distribution(
+    State(pools): State<AppState>,
+    Path(char_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
+        None => {
+            return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+        }
+    };
+
     let mut redis = pools.redis_pool.get().await.unwrap();
 
-    let latest_game_time = match imdb::get_latest_game_time(&mut redis).await {
-        Ok(latest_game_time) => Some(latest_game_time),
-        Err(_) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "latest_game_time does not exist!".to_string(),
-            ))
+    let (ts, distrubition_entry) = match imdb::get_char_distribution(char_id, &mut redis).await {
+        Ok(data) => data,
+        Err(e) => {
+            return Err((StatusCode::NOT_FOUND, e));
         }
     };
 
-    let now = chrono::Utc::now().timestamp();
+    let validators =
+        handlers::caching::timestamp_validators(&format!("distribution {}", char_id), &ts);
+
+    Ok(handlers::caching::cached_response(
+        &headers,
+        validators,
+        handlers::caching::DAILY_MAX_AGE,
+        Json(DistributionResponse {
+            timestamp: ts,
+            data: distrubition_entry,
+        }),
+    ))
+}
 
-    if now - 120 > latest_game_time.unwrap().and_utc().timestamp() {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "No New (2m) Replays!".to_string(),
-        ));
+async fn percentile(
+    State(pools): State<AppState>,
+    Path((player_id, char_id)): Path<(i64, String)>,
+) -> Result<Json<handlers::percentile::PercentileResponse>, (StatusCode, String)> {
+    let char_id = match characters::find(&char_id) {
+        Some(id) => id,
+        None => {
+            return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+        }
+    };
+
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    let percentile = match db::get_percentiles(player_id, Some(char_id), &mut db).await {
+        Ok(mut results) => match results.pop() {
+            Some(percentile) => percentile,
+            None => {
+                return Err((StatusCode::NOT_FOUND, "Player rating not found".to_string()));
+            }
+        },
+        Err(e) => return Err((StatusCode::NOT_FOUND, e)),
+    };
+
+    match handlers::percentile::handle_get_percentile(player_id, percentile).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err((StatusCode::NOT_FOUND, e)),
+    }
+}
+
+async fn player_events(
+    State(pools): State<AppState>,
+    Path(player_id): Path<i64>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<handlers::events::EventResponse>>, (StatusCode, String)> {
+    let mut db = pools.db_pool.get().await.unwrap();
+
+    match db::player_exists(&mut db, player_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err((StatusCode::NOT_FOUND, "Player not found".to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+
+    let count = pagination.count.unwrap_or(100) as i64;
+    let offset = pagination.offset.unwrap_or(0) as i64;
+
+    match db::get_events(player_id, count, offset, &mut db).await {
+        Ok(events) => Ok(Json(
+            events
+                .into_iter()
+                .map(handlers::events::event_response)
+                .collect(),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// Waits at most a few seconds, so a hung pool doesn't hang the health check with it.
+async fn health_timeout<F: std::future::Future<Output = Result<(), String>>>(
+    check: F,
+) -> Result<(), String> {
+    match tokio::time::timeout(std::time::Duration::from_secs(3), check).await {
+        Ok(result) => result,
+        Err(_) => Err("Timed out".to_string()),
+    }
+}
+
+async fn ping_postgres(pools: &AppState) -> Result<(), String> {
+    health_timeout(async {
+        match pools.db_pool.get().await {
+            Ok(mut db) => db::ping(&mut db).await,
+            Err(e) => Err(e.to_string()),
+        }
+    })
+    .await
+}
+
+async fn ping_redis(pools: &AppState) -> Result<(), String> {
+    health_timeout(async {
+        match pools.redis_pool.get().await {
+            Ok(mut redis) => imdb::ping(&mut redis).await,
+            Err(e) => Err(e.to_string()),
+        }
+    })
+    .await
+}
+
+async fn health(State(pools): State<AppState>) -> (StatusCode, Json<handlers::health::HealthResponse>) {
+    let postgres = ping_postgres(&pools).await;
+    let redis = ping_redis(&pools).await;
+
+    let (latest_game_time, last_update_hourly, last_update_daily) = match &redis {
+        Ok(()) => match pools.redis_pool.get().await {
+            Ok(mut redis) => (
+                imdb::get_latest_game_time(&mut redis).await.ok(),
+                imdb::get_last_update_hourly(&mut redis).await.ok(),
+                imdb::get_last_update_daily(&mut redis).await.ok(),
+            ),
+            Err(_) => (None, None, None),
+        },
+        Err(_) => (None, None, None),
+    };
+
+    let report = handlers::health::health_report(
+        handlers::health::HealthChecks {
+            postgres,
+            redis,
+            ggst_token: std::fs::exists("token.txt").unwrap_or(false),
+            latest_game_time,
+            last_update_hourly,
+            last_update_daily,
+        },
+        chrono::Utc::now().naive_utc(),
+    );
+
+    let status = if report.status == handlers::health::DOWN {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+
+    (status, Json(report))
+}
+
+/// The process is up and answering requests.
+async fn health_live() -> &'static str {
+    "OK"
+}
+
+/// Postgres and Redis are reachable, so requests can be served.
+async fn health_ready(State(pools): State<AppState>) -> Result<&'static str, (StatusCode, String)> {
+    if let Err(e) = ping_postgres(&pools).await {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Database unavailable: {e}")));
     }
 
-    let last_update_daily = imdb::get_last_update_daily(&mut redis).await.unwrap();
-    if now - 86400 > last_update_daily.and_utc().timestamp() {
-        return Ok(
-            "Daily Update Running. Replays are still being collected and will show up shortly."
-                .to_string(),
-        );
+    if let Err(e) = ping_redis(&pools).await {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Redis unavailable: {e}")));
     }
 
-    Ok("OK".to_string())
+    Ok("OK")
+}
+
+async fn live(
+    State(pools): State<AppState>,
+    Query(params): Query<handlers::live::LiveParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let char_id = match params.char_id {
+        Some(char_id) => match characters::find(&char_id) {
+            Some(id) => Some(id),
+            None => {
+                return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+            }
+        },
+        None => None,
+    };
+
+    //Clients that fall too far behind skip the games they missed
+    let stream = BroadcastStream::new(pools.live_games.subscribe()).filter_map(move |game| {
+        let game = game.ok()?;
+
+        if !handlers::live::matches_filter(&game, params.player_id, char_id, params.min_rating) {
+            return None;
+        }
+
+        Some(Ok(Event::default()
+            .event("game")
+            .data(serde_json::to_string(&game).unwrap())))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
 }
 
 // calc_rating endpoint removed - no longer needed with game-provided ratings
 
-async fn avatar(Path(player_id): Path<i64>, State(pools): State<AppState>) -> impl IntoResponse {
-    //If token.txt does not exist, return 503
-    if !std::fs::exists("token.txt").unwrap_or(false) {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
-            "GGST is not connected, patch?".to_string(),
-        ));
+/// The stored avatar, refreshed from GGST when it's due, and how long its variants can be cached.
+/// A stale avatar is still served when GGST can't be reached.
+async fn load_avatar(
+    player_id: i64,
+    redis: &mut RedisConnection<'_>,
+) -> Result<(String, i64), (StatusCode, String)> {
+    let cached = match crate::imdb::get_avatar(player_id, redis).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            warn!("{e}");
+            None
+        }
+    };
+
+    if let Some((png, ttl)) = &cached
+        && let Some(fresh) = handlers::avatar::fresh_for(*ttl)
+    {
+        return Ok((png.clone(), fresh));
     }
 
+    let fetched = if std::fs::exists("token.txt").unwrap_or(false) {
+        crate::ggst_api::get_player_avatar(player_id.to_string()).await
+    } else {
+        Err("GGST is not connected, patch?".to_string())
+    };
+
+    match (fetched, cached) {
+        (Ok(png), _) => {
+            if let Err(e) = crate::imdb::set_avatar(player_id, &png, redis).await {
+                warn!("{e}");
+            }
+            Ok((png, handlers::avatar::AVATAR_REFRESH_SECONDS))
+        }
+        (Err(e), Some((png, _))) => {
+            warn!("Avatar refresh for {player_id} failed: {e}");
+            Ok((png, handlers::avatar::STALE_RETRY_SECONDS))
+        }
+        (Err(e), None) => Err((StatusCode::SERVICE_UNAVAILABLE, e)),
+    }
+}
+
+async fn avatar(
+    Path(player_id): Path<i64>,
+    Query(params): Query<handlers::avatar::AvatarParams>,
+    State(pools): State<AppState>,
+    request_headers: HeaderMap,
+) -> impl IntoResponse {
+    let size = match handlers::avatar::parse_size(params.size) {
+        Ok(size) => size,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
+    };
+    let format = handlers::avatar::negotiate_format(&request_headers);
+    let variant = handlers::avatar::variant_key(size, format);
+
     let mut db = pools.db_pool.get().await.unwrap();
     let mut redis = pools.redis_pool.get().await.unwrap();
 
@@ -733,24 +1244,56 @@ async fn avatar(Path(player_id): Path<i64>, State(pools): State<AppState>) -> im
         return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
     }
 
-    let png = match crate::imdb::get_avatar(player_id, &mut redis).await {
-        Ok(avatar) => avatar,
-        Err(_) => match crate::ggst_api::get_player_avatar(player_id.to_string()).await {
-            Ok(png) => {
-                let _ = crate::imdb::set_avatar(player_id, &png, &mut redis).await;
-                png
+    let output = match crate::imdb::get_avatar_variant(player_id, &variant, &mut redis).await {
+        Ok(Some(output)) => output,
+        cached => {
+            if let Err(e) = cached {
+                warn!("{e}");
             }
-            Err(e) => return Err((StatusCode::SERVICE_UNAVAILABLE, e)),
-        },
+
+            let (png, cache_seconds) = load_avatar(player_id, &mut redis).await?;
+
+            //The placeholder is cached like the avatar, so a corrupt one isn't decoded again until it's refreshed
+            let output = match handlers::avatar::process_avatar(&png, size, format) {
+                Ok(output) => output,
+                Err(e) => {
+                    warn!("Avatar for {player_id}: {e}");
+                    handlers::avatar::placeholder(size, format)
+                }
+            };
+
+            if let Err(e) = crate::imdb::set_avatar_variant(
+                player_id,
+                &variant,
+                &output,
+                cache_seconds,
+                &mut redis,
+            )
+            .await
+            {
+                warn!("{e}");
+            }
+
+            output
+        }
     };
 
-    let output = crate::handlers::avatar::handle_get_avatar(png).await;
+    let validators = handlers::caching::content_validators(output.as_slice());
 
-    // Create response headers
     let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
-
-    Ok((headers, output))
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+
+    Ok(handlers::caching::cached_response_with_headers(
+        &request_headers,
+        validators,
+        handlers::caching::AVATAR_MAX_AGE,
+        headers,
+        output,
+    ))
 }
 
 fn init_tracing(prefix: &str) -> WorkerGuard {
@@ -794,9 +1337,68 @@ fn init_tracing(prefix: &str) -> WorkerGuard {
     guard
 }
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 5)]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenv::dotenv().expect("Failed to read .env file");
+fn main() -> ExitCode {
+    let cli = cli::Cli::parse();
+
+    //.env is optional now that settings can come from the config file
+    dotenv::dotenv().ok();
+
+    //No command starts the web server
+    let uses_ggst = cli.command.as_ref().is_none_or(cli::Command::uses_ggst);
+    let config = match config::init(cli.config.as_deref(), uses_ggst) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration:\n{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.worker_threads)
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start the runtime: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match runtime.block_on(run(cli, config)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// For the one-off commands, the long running ones log to files instead.
+fn init_stdout_tracing() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+}
+
+async fn run(
+    cli: cli::Cli,
+    config: &'static config::Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let command = cli.command.unwrap_or(cli::Command::Serve);
+    if cli.dry_run && !command.supports_dry_run() {
+        return Err(format!("--dry-run isn't supported by {}", command.name()).into());
+    }
+
+    //Checked before connecting, the character registry needs the latest tables
+    if let cli::Command::Migrate = command {
+        init_stdout_tracing();
+        migrations::run(config.database.url.clone(), cli.dry_run).await?;
+        return Ok(());
+    }
+    if let Some(warning) = migrations::check_schema(config.database.url.clone()).await? {
+        eprintln!("Warning: {warning}");
+    }
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -812,68 +1414,133 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .allow_headers(vec![ACCEPT, AUTHORIZATION, CONTENT_TYPE, ORIGIN]);
 
     // set up connection pool
-    let config = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(
-        std::env::var("DATABASE_URL").expect("DATABASE_URL"),
+    let manager = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(
+        config.database.url.clone(),
     );
 
     //Postgres
-    let pool = bb8::Pool::builder().build(config).await?;
+    let pool = bb8::Pool::builder()
+        .max_size(config.database.pool_size)
+        .build(manager)
+        .await?;
 
     //Redis
-    let manager =
-        RedisConnectionManager::new(std::env::var("REDIS_URL").expect("REDIS_URL")).unwrap();
+    let manager = RedisConnectionManager::new(config.redis.url.clone())?;
     let redis_pool = bb8::Pool::builder().build(manager).await.unwrap();
 
     let state = AppState {
         db_pool: pool,
         redis_pool,
+        live_games: tokio::sync::broadcast::channel(256).0,
     };
 
-    let args = std::env::args().skip(1).collect::<Vec<_>>();
-    match args.get(0).map(|r| r.deref()) {
+    let mut db = state.db_pool.get().await?;
+    characters::load(&mut db).await?;
+    drop(db);
+
+    match command {
         //This runs the timed jobs: grab replay, update ratings, update ranking, etc.
-        Some("pull") => {
+        cli::Command::Pull => {
             let _guard = init_tracing("pull");
 
             // We have these declared here too so that we can change the connection pool settings
 
             // set up connection pool
-            let config = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(
-                std::env::var("DATABASE_URL").expect("DATABASE_URL"),
+            let manager = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(
+                config.database.url.clone(),
             );
 
-            //Postgres - reduce max size to 4
-            let pool = bb8::Pool::builder().max_size(4).build(config).await?;
+            //Postgres - smaller pool than the web server
+            let pool = bb8::Pool::builder()
+                .max_size(config.database.pull_pool_size)
+                .build(manager)
+                .await?;
 
             //Redis
-            let manager =
-                RedisConnectionManager::new(std::env::var("REDIS_URL").expect("REDIS_URL"))
-                    .unwrap();
+            let manager = RedisConnectionManager::new(config.redis.url.clone())?;
             let redis_pool = bb8::Pool::builder().build(manager).await.unwrap();
 
             let state = AppState {
                 db_pool: pool,
                 redis_pool,
+                live_games: tokio::sync::broadcast::channel(256).0,
             };
 
-            pull::pull_and_update_continuous(state).await;
+            let metrics = monitoring::install();
+            tokio::spawn(monitoring::serve(
+                config.pull.metrics_addr.clone(),
+                metrics,
+            ));
+            tokio::spawn(monitoring::record_pool_usage(state.clone()));
+
+            pull::pull_and_update_continuous(state).await?
+        }
+        cli::Command::Hourly => {
+            init_stdout_tracing();
+            pull::do_hourly_update_once(state).await?
+        }
+        cli::Command::Daily => {
+            init_stdout_tracing();
+            pull::do_daily_update_once(state).await?
+        }
+        cli::Command::Migrate => unreachable!("migrate returns before connecting"),
+        //Recomputes player_char_stats from every stored game, needed once after the migration
+        cli::Command::Backfill => {
+            init_stdout_tracing();
+            pull::rebuild_player_char_stats(state, cli.dry_run).await?
         }
-        Some("hourly") => {
-            tracing_subscriber::fmt()
-                .with_max_level(tracing::Level::INFO)
-                .init();
-            pull::do_hourly_update_once(state).await
+        cli::Command::RebuildRanks => {
+            init_stdout_tracing();
+            pull::rebuild_ranks_once(state, cli.dry_run).await?
         }
-        Some("daily") => {
-            tracing_subscriber::fmt()
-                .with_max_level(tracing::Level::INFO)
-                .init();
-            pull::do_daily_update_once(state).await
+        cli::Command::SyncPlayer { player_id } => {
+            init_stdout_tracing();
+            cli::sync_player(state, player_id, cli.dry_run).await?
         }
-        _ => {
-            // No args, run the web server
+        cli::Command::FetchAvatar { player_id } => {
+            init_stdout_tracing();
+            cli::fetch_avatar(state, player_id, cli.dry_run).await?
+        }
+        //Logs the webhooks it receives, to test subscriptions without a real endpoint
+        cli::Command::WebhookReceiver { addr } => {
+            init_stdout_tracing();
+            webhooks::run_test_receiver(&addr).await?
+        }
+        cli::Command::Serve => {
             let _guard = init_tracing("web");
 
+            let metrics = monitoring::install();
+            tokio::spawn(monitoring::record_pool_usage(state.clone()));
+
+            tokio::spawn(imdb::subscribe_games(
+                config.redis.url.clone(),
+                state.live_games.clone(),
+            ));
+
+            //Pick up characters registered by the pull process since startup
+            let registry_pool = state.db_pool.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    config.web.character_reload_seconds,
+                ));
+
+                loop {
+                    interval.tick().await;
+
+                    let mut db = match registry_pool.get().await {
+                        Ok(db) => db,
+                        Err(e) => {
+                            warn!("Failed to reload characters: {e}");
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = characters::load(&mut db).await {
+                        warn!("{e}");
+                    }
+                }
+            });
+
             let mut app = Router::new()
                 .route("/api/player/:id", get(player))
                 .route(
@@ -885,20 +1552,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .route("/api/characters", get(characters))
                 .route("/api/player/search", get(player_search))
                 .route("/api/rating_sync/:player_id", get(rating_sync))
+                .route(
+                    "/api/rating_sync/:player_id/status",
+                    get(rating_sync_status),
+                )
                 .route("/api/settings/:key", get(settings))
                 .route("/api/alias/:player_id", get(alias))
+                .route(
+                    "/api/webhooks/:key",
+                    get(webhooks_list).post(webhook_create),
+                )
+                .route("/api/webhooks/:key/:webhook_id", delete(webhook_delete))
                 .route("/api/ratings/:player_id/:char_id/:duration", get(ratings))
                 .route("/api/stats", get(stats))
                 .route("/api/popularity", get(popularity))
+                .route("/api/trends", get(trends))
+                .route("/api/trends/:char_id", get(trends_char))
                 .route("/api/matchups", get(matchups))
                 .route(
                     "/api/matchups/:player_id/:char_id/:duration",
                     get(player_matchups),
                 )
+                .route("/api/patches", get(patches))
                 .route("/api/supporters", get(supporters))
                 .route("/api/distribution", get(distribution))
+                .route("/api/distribution/:char_id", get(char_distribution))
+                .route("/api/percentile/:player_id/:char_id", get(percentile))
+                .route("/api/events/:player_id", get(player_events))
                 .route("/api/health", get(health))
+                .route("/api/health/live", get(health_live))
+                .route("/api/health/ready", get(health_ready))
                 .route("/api/avatar/:player_id", get(avatar))
+                .route("/api/live", get(live))
+                .route("/metrics", get(move || std::future::ready(metrics.render())))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit::limit_requests,
+                ))
+                .layer(axum::middleware::from_fn(monitoring::track_requests))
                 .with_state(state);
 
             if cfg!(debug_assertions) {
@@ -907,8 +1598,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 app = app.layer(cors);
             }
 
-            let listener = tokio::net::TcpListener::bind(std::env::var("LISTEN_ADDR").expect("LISTEN_ADDR")).await?;
-            axum::serve(listener, app).await?;
+            let listener = tokio::net::TcpListener::bind(&config.web.listen_addr).await?;
+            //The rate limiter falls back to the connection's address
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .await?;
         }
     }
 
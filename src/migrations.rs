@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+
+use diesel::migration::MigrationSource;
+use diesel::pg::Pg;
+use diesel::{Connection, PgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use tracing::info;
+
+/// Everything in `migrations/`, built into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Migrations use a blocking connection, diesel-async can't run them.
+async fn with_connection<T: Send + 'static>(
+    database_url: String,
+    f: impl FnOnce(&mut PgConnection) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    let task = tokio::task::spawn_blocking(move || {
+        let mut connection = match PgConnection::establish(&database_url) {
+            Ok(connection) => connection,
+            Err(e) => return Err(format!("Failed to connect to the database: {e}")),
+        };
+
+        f(&mut connection)
+    });
+
+    match task.await {
+        Ok(result) => result,
+        Err(e) => Err(format!("Migration task failed: {e}")),
+    }
+}
+
+fn pending_names(connection: &mut PgConnection) -> Result<Vec<String>, String> {
+    match connection.pending_migrations(MIGRATIONS) {
+        Ok(pending) => Ok(pending.iter().map(|m| m.name().to_string()).collect()),
+        Err(e) => Err(format!("Failed to read applied migrations: {e}")),
+    }
+}
+
+/// Applies every pending migration, or only lists them for a dry run.
+pub async fn run(database_url: String, dry_run: bool) -> Result<(), String> {
+    with_connection(database_url, move |connection| {
+        let pending = pending_names(connection)?;
+
+        if pending.is_empty() {
+            info!("Database schema is up to date");
+            return Ok(());
+        }
+
+        for name in &pending {
+            info!("Pending migration {name}");
+        }
+
+        if dry_run {
+            return Ok(());
+        }
+
+        match connection.run_pending_migrations(MIGRATIONS) {
+            Ok(applied) => {
+                info!("Applied {} migrations", applied.len());
+                Ok(())
+            }
+            Err(e) => Err(format!("Migration failed: {e}")),
+        }
+    })
+    .await
+}
+
+/// Fails when the database is missing migrations this binary was built with.
+/// A database ahead of the binary is only a warning, so an older build can still be rolled back to.
+/// It's returned rather than logged, this runs before tracing is set up.
+pub async fn check_schema(database_url: String) -> Result<Option<String>, String> {
+    with_connection(database_url, |connection| {
+        let pending = pending_names(connection)?;
+
+        if !pending.is_empty() {
+            return Err(format!(
+                "Database schema is outdated, {} pending migrations ({}). Run `puddle-farm migrate` first.",
+                pending.len(),
+                pending.join(", ")
+            ));
+        }
+
+        let known = match MigrationSource::<Pg>::migrations(&MIGRATIONS) {
+            Ok(migrations) => migrations
+                .iter()
+                .map(|m| m.name().version().to_string())
+                .collect::<HashSet<_>>(),
+            Err(e) => return Err(format!("Failed to read embedded migrations: {e}")),
+        };
+
+        match connection.applied_migrations() {
+            Ok(applied) => {
+                let unknown = applied
+                    .iter()
+                    .filter(|v| !known.contains(&v.to_string()))
+                    .count();
+                if unknown > 0 {
+                    return Ok(Some(format!(
+                        "Database has {unknown} migrations this build doesn't know about"
+                    )));
+                }
+                Ok(None)
+            }
+            Err(e) => Err(format!("Failed to read applied migrations: {e}")),
+        }
+    })
+    .await
+}
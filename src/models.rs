@@ -13,10 +13,21 @@ use diesel::{
     prelude::*,
 };
 use crate::schema::{
-    self, character_ranks, games, global_ranks, player_names, players, tags, player_ratings,
+    self, character_daily_stats, character_ranks, characters, events, games, global_ranks, patches,
+    player_char_stats, player_names, player_official_stats, players, rank_tiers, tags,
+    player_ratings, webhooks,
 };
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
+#[derive(Selectable, Insertable, Queryable, Identifiable)]
+#[diesel(primary_key(day, char_id))]
+pub struct CharacterDailyStat {
+    pub day: NaiveDate,
+    pub char_id: i16,
+    pub games: i64,
+    pub players: i64,
+}
+
 #[derive(Selectable, Insertable, Queryable, Identifiable)]
 #[diesel(primary_key(rank, char_id))]
 pub struct CharacterRank {
@@ -25,6 +36,39 @@ pub struct CharacterRank {
     pub rank: i32,
 }
 
+#[derive(Selectable, Insertable, Queryable, Identifiable, Clone)]
+pub struct Character {
+    pub id: i16,
+    pub short: String,
+    pub name: String,
+    pub json_code: String,
+    pub release_date: Option<NaiveDate>,
+}
+
+#[derive(Selectable, Queryable, Identifiable)]
+pub struct Event {
+    pub id: i64,
+    pub player_id: i64,
+    pub char_id: i16,
+    pub kind: String,
+    pub value: i64,
+    pub previous_value: Option<i64>,
+    pub tier: Option<String>,
+    pub timestamp: NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = events)]
+pub struct NewEvent {
+    pub player_id: i64,
+    pub char_id: i16,
+    pub kind: String,
+    pub value: i64,
+    pub previous_value: Option<i64>,
+    pub tier: Option<String>,
+    pub timestamp: NaiveDateTime,
+}
+
 #[derive(Selectable, Insertable, Queryable, Identifiable)]
 #[diesel(primary_key(timestamp, id_a, id_b))]
 pub struct Game {
@@ -42,6 +86,7 @@ pub struct Game {
     pub value_a: i64,
     pub value_b: i64,
     pub real_timestamp: Option<NaiveDateTime>,
+    pub version: Option<String>,
 }
 
 #[derive(Selectable, Insertable, Queryable, Identifiable)]
@@ -52,6 +97,45 @@ pub struct GlobalRank {
     pub char_id: i16,
 }
 
+#[derive(Selectable, Insertable, Queryable, Identifiable)]
+#[diesel(table_name = patches, primary_key(version))]
+pub struct Patch {
+    pub version: String,
+    pub start_date: NaiveDateTime,
+    pub label: String,
+}
+
+#[derive(Selectable, Insertable, Queryable, Identifiable)]
+#[diesel(table_name = player_char_stats, primary_key(id, char_id))]
+pub struct PlayerCharStat {
+    pub id: i64,
+    pub char_id: i16,
+    pub games: i64,
+    pub wins: i64,
+    pub losses: i64,
+    pub peak_rating: i64,
+    pub peak_timestamp: NaiveDateTime,
+    pub top_defeated_id: Option<i64>,
+    pub top_defeated_name: Option<String>,
+    pub top_defeated_char: Option<i16>,
+    pub top_defeated_value: Option<i64>,
+    pub top_defeated_timestamp: Option<NaiveDateTime>,
+    pub first_played: NaiveDateTime,
+    pub last_played: NaiveDateTime,
+}
+
+/// A player's official totals on a character, as the GGST statistics api reports them.
+#[derive(Selectable, Insertable, Queryable, Identifiable, Clone, Debug, PartialEq)]
+#[diesel(table_name = player_official_stats, primary_key(id, char_id))]
+pub struct PlayerOfficialStat {
+    pub id: i64,
+    pub char_id: i16,
+    pub level: Option<i32>,
+    pub ranked_games: Option<i32>,
+    pub ranked_wins: Option<i32>,
+    pub updated_at: NaiveDateTime,
+}
+
 #[derive(Selectable, Insertable, Queryable, Identifiable)]
 #[diesel(primary_key(id, name))]
 pub struct PlayerName {
@@ -76,10 +160,30 @@ pub struct Player {
     pub rcode_check_code: Option<String>,
 }
 
+#[derive(Selectable, Insertable, Queryable, Identifiable, Clone)]
+#[diesel(primary_key(lower_bound))]
+pub struct RankTier {
+    pub lower_bound: i32,
+    pub upper_bound: i32,
+    pub name: String,
+}
+
 #[derive(Selectable, Insertable, Queryable)]
 pub struct Tag {
     pub id: i32,
     pub player_id: i64,
     pub tag: String,
     pub style: String,
-}
\ No newline at end of file
+}
+
+#[derive(Selectable, Queryable, Identifiable, Clone)]
+pub struct Webhook {
+    pub id: i32,
+    pub owner_id: i64,
+    pub target_id: i64,
+    pub url: String,
+    pub on_game: bool,
+    pub on_rating_high: bool,
+    pub on_top_100: bool,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+    routing::get,
+    Router,
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tracing::info;
+
+/// Buckets in seconds, shared by request latencies and pull job durations.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Installs the process wide recorder, anything recorded before this is dropped.
+pub fn install() -> PrometheusHandle {
+    let handle = PrometheusBuilder::new()
+        .set_buckets(DURATION_BUCKETS)
+        .unwrap()
+        .install_recorder()
+        .unwrap();
+
+    //Without the built in listener, histograms have to be drained by hand
+    let upkeep = handle.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+
+        loop {
+            interval.tick().await;
+            upkeep.run_upkeep();
+        }
+    });
+
+    handle
+}
+
+/// Serves `/metrics` on its own listener, for processes without a web server.
+pub async fn serve(addr: String, handle: PrometheusHandle) {
+    let app = Router::new().route("/metrics", get(move || std::future::ready(handle.render())));
+
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind metrics listener on {addr}: {e}");
+            return;
+        }
+    };
+
+    info!("Metrics listening on {addr}");
+    if let Err(e) = axum::serve(listener, app).await {
+        tracing::error!("Metrics listener failed: {e}");
+    }
+}
+
+/// Records the latency and status of every request, by route rather than by path.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string(),
+    };
+    let method = request.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    histogram!(
+        "http_request_duration_seconds",
+        "route" => route.clone(),
+        "method" => method.clone()
+    )
+    .record(start.elapsed().as_secs_f64());
+    counter!(
+        "http_requests_total",
+        "route" => route,
+        "method" => method,
+        "status" => response.status().as_u16().to_string()
+    )
+    .increment(1);
+
+    response
+}
+
+/// Samples how many database and Redis connections are in use.
+pub async fn record_pool_usage(state: crate::AppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        for (pool, pool_state) in [
+            ("db", state.db_pool.state()),
+            ("redis", state.redis_pool.state()),
+        ] {
+            gauge!("pool_connections", "pool" => pool, "state" => "idle")
+                .set(pool_state.idle_connections as f64);
+            gauge!("pool_connections", "pool" => pool, "state" => "in_use")
+                .set((pool_state.connections - pool_state.idle_connections) as f64);
+        }
+    }
+}
+
+/// Records how long a pull job took when dropped, whichever way the job returns.
+pub struct JobTimer {
+    job: &'static str,
+    start: Instant,
+}
+
+impl JobTimer {
+    pub fn start(job: &'static str) -> JobTimer {
+        JobTimer {
+            job,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for JobTimer {
+    fn drop(&mut self) {
+        histogram!("pull_job_duration_seconds", "job" => self.job)
+            .record(self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// Counts a failed call to the GGST API.
+pub fn ggst_api_error(endpoint: &'static str) {
+    counter!("ggst_api_errors_total", "endpoint" => endpoint).increment(1);
+}
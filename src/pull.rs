@@ -1,35 +1,45 @@
-use crate::{ggst_api, schema::{self, player_ratings}, CHAR_NAMES};
+use crate::{characters, ggst_api, schema::{self, player_ratings}};
 
 use bb8_redis::redis;
 use diesel::prelude::*;
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::time::Duration;
 use tokio::time;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::schema::{character_ranks, games, global_ranks, player_names, players};
-use chrono::{NaiveDateTime, Utc};
+use crate::schema::{character_ranks, games, global_ranks, patches, player_names, players};
+use chrono::{Months, NaiveDateTime, Utc};
 use diesel::dsl::*;
 
 use crate::models::*;
+use crate::handlers::events::RatingState;
+use crate::handlers::live::{live_game, LiveGame};
+use crate::handlers::rating_sync::{
+    finished_status, next_refresh_batch, rating_sync_status, sync_player_stats, RatingSyncDiff,
+    RUNNING,
+};
 
 use diesel_async::scoped_futures::ScopedFutureExt;
 
-use diesel::sql_types::{BigInt, Integer};
+use diesel::sql_types::{BigInt, Integer, Nullable, SmallInt, Text, Timestamp};
+
+use crate::db::GameScope;
 
 define_sql_function! {
     fn coalesce(x: diesel::sql_types::Nullable<diesel::sql_types::Timestamp>, y: diesel::sql_types::Timestamp) -> diesel::sql_types::Timestamp;
 }
 
-pub const ONE_MINUTE: u64 = 1 * 60;
-
 //TODO move the db stuff from this file into db.rs and imdb.rs
 
-pub async fn pull_and_update_continuous(state: crate::AppState) {
+/// Runs the pull loops until one of them stops, which is always an error.
+pub async fn pull_and_update_continuous(state: crate::AppState) -> Result<(), String> {
     // Processing loop
     let processing_state = state.clone();
     let processing_task = tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(ONE_MINUTE - 10));
+        let mut interval = time::interval(Duration::from_secs(
+            crate::config::get().pull.processing_interval_seconds,
+        ));
 
         loop {
             interval.tick().await;
@@ -69,7 +79,10 @@ pub async fn pull_and_update_continuous(state: crate::AppState) {
                 if let Err(e) = connection
                     .transaction::<_, diesel::result::Error, _>(|conn| {
                         async move {
-                            do_hourly_update(conn, &mut redis_connection).await.unwrap();
+                            //The jobs that succeeded are still committed
+                            if let Err(e) = do_hourly_update(conn, &mut redis_connection).await {
+                                error!("Hourly update: {e}");
+                            }
                             Ok(())
                         }
                         .scope_boxed()
@@ -113,7 +126,10 @@ pub async fn pull_and_update_continuous(state: crate::AppState) {
                 if let Err(e) = connection
                     .transaction::<_, diesel::result::Error, _>(|conn| {
                         async move {
-                            do_daily_update(conn, &mut redis_connection).await.unwrap();
+                            //The jobs that succeeded are still committed
+                            if let Err(e) = do_daily_update(conn, &mut redis_connection).await {
+                                error!("Daily update: {e}");
+                            }
                             Ok(())
                         }
                         .scope_boxed()
@@ -133,7 +149,9 @@ pub async fn pull_and_update_continuous(state: crate::AppState) {
     // Replay pulling loop
     let pull_state = state.clone();
     let pull_task = tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(ONE_MINUTE));
+        let mut interval = time::interval(Duration::from_secs(
+            crate::config::get().pull.replay_interval_seconds,
+        ));
 
         loop {
             interval.tick().await;
@@ -143,80 +161,418 @@ pub async fn pull_and_update_continuous(state: crate::AppState) {
             let mut connection = pull_state.db_pool.get().await.unwrap();
             let mut redis_connection = pull_state.redis_pool.get().await.unwrap();
 
-            if let Err(e) = connection
+            let new_games = connection
                 .transaction::<_, diesel::result::Error, _>(|conn| {
                     async move {
                         match grab_games(conn, &mut redis_connection).await {
                             Ok(new_games) => {
                                 info!("New games: {:?}", new_games.len());
+                                Ok(new_games)
                             }
                             Err(e) => {
                                 error!("grab_games failed: {e}");
+                                Err(diesel::result::Error::RollbackTransaction)
                             }
-                        };
-                        Ok(())
+                        }
                     }
                     .scope_boxed()
                 })
-                .await
-            {
-                error!("Replay pull loop: {e}");
+                .await;
+
+            //Only count and publish once the games are committed
+            match new_games {
+                Ok(new_games) => {
+                    metrics::counter!("games_inserted_total").increment(new_games.len() as u64);
+
+                    let mut redis_connection = pull_state.redis_pool.get().await.unwrap();
+
+                    let live_games: Vec<LiveGame> = new_games.iter().map(live_game).collect();
+
+                    if let Err(e) =
+                        crate::imdb::publish_games(&live_games, &mut redis_connection).await
+                    {
+                        error!("publish_games failed: {e}");
+                    }
+
+                    let player_ids: HashSet<i64> =
+                        new_games.iter().flat_map(|g| [g.id_a, g.id_b]).collect();
+                    let player_ids: Vec<i64> = player_ids.into_iter().collect();
+                    if let Err(e) =
+                        crate::imdb::invalidate_players(&player_ids, &mut redis_connection).await
+                    {
+                        error!("invalidate_players failed: {e}");
+                    }
+
+                    if let Err(e) = crate::webhooks::queue_game_events(
+                        &new_games,
+                        &mut connection,
+                        &mut redis_connection,
+                    )
+                    .await
+                    {
+                        error!("queue_game_events failed: {e}");
+                    }
+                }
+                Err(e) => {
+                    error!("Replay pull loop: {e}");
+                }
             }
 
             info!("Replay pull - Done");
         }
     });
 
-    tokio::select! {
-        _ = processing_task => {},
-        _ = pull_task => {},
+    // Webhook delivery loop
+    let webhook_state = state.clone();
+    let webhook_task = tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(
+            crate::config::get().pull.webhook_interval_seconds,
+        ));
+        let client = crate::webhooks::delivery_client();
+
+        loop {
+            interval.tick().await;
+
+            let mut connection = webhook_state.db_pool.get().await.unwrap();
+            let mut redis_connection = webhook_state.redis_pool.get().await.unwrap();
+
+            if let Err(e) =
+                crate::webhooks::deliver_due(&client, &mut connection, &mut redis_connection).await
+            {
+                error!("deliver_due failed: {e}");
+            }
+        }
+    });
+
+    // Rating sync loop
+    let rating_sync_state = state.clone();
+    let rating_sync_task = tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(
+            crate::config::get().pull.rating_sync_interval_seconds,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            let mut connection = rating_sync_state.db_pool.get().await.unwrap();
+            let mut redis_connection = rating_sync_state.redis_pool.get().await.unwrap();
+
+            if let Err(e) = process_rating_syncs(&mut connection, &mut redis_connection).await {
+                error!("process_rating_syncs failed: {e}");
+            }
+        }
+    });
+
+    // Scheduled rating refresh loop
+    let refresh_state = state.clone();
+    let refresh_task = tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(
+            crate::config::get().pull.refresh_interval_seconds,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            let mut connection = refresh_state.db_pool.get().await.unwrap();
+            let mut redis_connection = refresh_state.redis_pool.get().await.unwrap();
+
+            if let Err(e) = refresh_ratings(&mut connection, &mut redis_connection).await {
+                error!("refresh_ratings failed: {e}");
+            }
+        }
+    });
+
+    //The tasks loop forever, one ending means it panicked
+    let (task, result) = tokio::select! {
+        r = processing_task => ("processing", r),
+        r = pull_task => ("pull", r),
+        r = webhook_task => ("webhook", r),
+        r = rating_sync_task => ("rating sync", r),
+        r = refresh_task => ("refresh", r),
+    };
+
+    match result {
+        Ok(()) => Err(format!("The {task} task stopped")),
+        Err(e) => Err(format!("The {task} task failed: {e}")),
     }
 }
 
-pub async fn do_hourly_update_once(state: crate::AppState) {
-    let mut connection = state.db_pool.get().await.unwrap();
-    let mut redis_connection = state.redis_pool.get().await.unwrap();
+/// Token bucket every player stats request draws from, queued and scheduled syncs alike.
+const STATS_REQUEST_BUDGET: &str = "ggst_player_stats";
+
+/// A queued sync gets the player's stats and their avatar.
+const REQUESTS_PER_SYNC: u32 = 2;
+
+/// Whether the request budget has room for `count` more GGST requests, taking them if it has.
+/// Without a budget there's no refresh, and queued syncs aren't limited.
+async fn take_stats_requests(
+    count: u32,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<bool, String> {
+    let per_minute = crate::config::get().pull.refresh_requests_per_minute;
+    if per_minute == 0 {
+        return Ok(true);
+    }
 
-    connection
-        .transaction::<_, diesel::result::Error, _>(|conn| {
-            async move {
-                do_hourly_update(conn, &mut redis_connection).await.unwrap();
-                Ok(())
-            }
-            .scope_boxed()
-        })
+    //A budget smaller than the cost would never fill up enough
+    let retry_after = crate::imdb::take_rate_limit_tokens(
+        STATS_REQUEST_BUDGET,
+        per_minute,
+        per_minute,
+        count.min(per_minute),
+        redis,
+    )
+    .await?;
+
+    Ok(retry_after.is_none())
+}
+
+/// Leaderboard players and everyone who played recently, sorted by id.
+async fn get_refresh_candidates(
+    active_days: i64,
+    conn: &mut crate::Connection<'_>,
+) -> Result<Vec<i64>, String> {
+    let top: Vec<i64> = match global_ranks::table
+        .select(global_ranks::id)
+        .load(conn)
         .await
-        .unwrap();
+    {
+        Ok(top) => top,
+        Err(e) => return Err(format!("Error loading global ranks: {e}")),
+    };
+
+    let since = Utc::now().naive_utc() - chrono::Duration::days(active_days);
+    let active: Vec<i64> = match schema::player_char_stats::table
+        .filter(schema::player_char_stats::last_played.ge(since))
+        .select(schema::player_char_stats::id)
+        .distinct()
+        .load(conn)
+        .await
+    {
+        Ok(active) => active,
+        Err(e) => return Err(format!("Error loading active players: {e}")),
+    };
+
+    Ok(top
+        .into_iter()
+        .chain(active)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+/// Syncs the next leaderboard and recently active players in turn, as far as the request
+/// budget allows. Players synced in the last minute are skipped until the next round.
+async fn refresh_ratings(
+    conn: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    let config = &crate::config::get().pull;
+    if config.refresh_requests_per_minute == 0 || !std::fs::exists("token.txt").unwrap_or(false) {
+        return Ok(());
+    }
+
+    let candidates = get_refresh_candidates(config.refresh_active_days, conn).await?;
+    let mut cursor = crate::imdb::get_rating_refresh_cursor(redis).await?;
+    let batch = next_refresh_batch(
+        &candidates,
+        cursor,
+        config.refresh_requests_per_minute as usize,
+    );
+
+    let mut refreshed = vec![];
+    let mut updated = 0;
+
+    for player_id in batch {
+        if crate::imdb::try_start_rating_sync(player_id, redis).await?.is_some() {
+            cursor = player_id;
+            continue;
+        }
+
+        if !take_stats_requests(1, redis).await? {
+            crate::imdb::release_rating_sync(player_id, redis).await?;
+            break;
+        }
+        cursor = player_id;
+
+        match fetch_player_stats(player_id, conn).await {
+            Ok(diff) => {
+                if !diff.ratings.is_empty() {
+                    updated += 1;
+                }
+                refreshed.push(player_id);
+            }
+            Err(e) => warn!("Rating refresh for {player_id} failed: {e}"),
+        }
+    }
+
+    crate::imdb::set_rating_refresh_cursor(cursor, redis).await?;
+
+    if let Err(e) = crate::imdb::invalidate_players(&refreshed, redis).await {
+        warn!("{e}");
+    }
+
+    metrics::counter!("rating_refreshes_total").increment(refreshed.len() as u64);
+    info!(
+        "Refreshed {} of {} players, {} with new ratings",
+        refreshed.len(),
+        candidates.len(),
+        updated
+    );
+
+    Ok(())
 }
 
-pub async fn do_daily_update_once(state: crate::AppState) {
-    let mut connection = state.db_pool.get().await.unwrap();
-    let mut redis_connection = state.redis_pool.get().await.unwrap();
+/// Works through the syncs queued by the web server, one at a time and within the request budget
+/// to go easy on the GGST api.
+async fn process_rating_syncs(
+    conn: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    while let Some(player_id) = crate::imdb::pop_rating_sync(redis).await? {
+        //Out of budget, the player waits at the front of the queue for the next run
+        if !take_stats_requests(REQUESTS_PER_SYNC, redis).await? {
+            crate::imdb::requeue_rating_sync(player_id, redis).await?;
+            break;
+        }
 
-    connection
-        .transaction::<_, diesel::result::Error, _>(|conn| {
+        let running = rating_sync_status(RUNNING, "Syncing");
+        crate::imdb::set_rating_sync_status(player_id, &running, redis).await?;
+
+        let result = sync_player_ratings(player_id, conn, redis).await;
+        if let Err(e) = &result {
+            warn!("Rating sync for {player_id} failed: {e}");
+        }
+
+        crate::imdb::set_rating_sync_status(player_id, &finished_status(result), redis).await?;
+    }
+
+    Ok(())
+}
+
+/// Updates the player's ratings, official totals, name and platform and refreshes their
+/// cached avatar.
+pub async fn sync_player_ratings(
+    player_id: i64,
+    conn: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<RatingSyncDiff, String> {
+    let mut diff = fetch_player_stats(player_id, conn).await?;
+
+    //A stale avatar isn't worth failing the sync over
+    match ggst_api::get_player_avatar(player_id.to_string()).await {
+        Ok(png) => match crate::imdb::set_avatar(player_id, &png, redis).await {
+            Ok(()) => diff.avatar_refreshed = true,
+            Err(e) => warn!("{e}"),
+        },
+        Err(e) => warn!("Avatar refresh for {player_id} failed: {e}"),
+    }
+
+    if let Err(e) = crate::imdb::invalidate_players(&[player_id], redis).await {
+        warn!("{e}");
+    }
+
+    Ok(diff)
+}
+
+/// Updates the player's ratings, official totals, name and platform.
+async fn fetch_player_stats(
+    player_id: i64,
+    conn: &mut crate::Connection<'_>,
+) -> Result<RatingSyncDiff, String> {
+    let json_response = get_player_stats_json(player_id).await?;
+
+    sync_player_stats(player_id, &json_response, conn).await
+}
+
+/// What a sync would change in the database, rolled back afterwards. The avatar isn't fetched.
+pub async fn preview_player_sync(
+    player_id: i64,
+    conn: &mut AsyncPgConnection,
+) -> Result<RatingSyncDiff, String> {
+    let json_response = get_player_stats_json(player_id).await?;
+
+    let mut diff = RatingSyncDiff::default();
+    let result = conn
+        .transaction::<(), Rollback, _>(|conn| {
+            let diff = &mut diff;
             async move {
-                do_daily_update(conn, &mut redis_connection).await.unwrap();
-                Ok(())
+                match sync_player_stats(player_id, &json_response, conn).await {
+                    Ok(synced) => *diff = synced,
+                    Err(e) => return Err(Rollback::Failed(e)),
+                }
+
+                Err(Rollback::DryRun)
             }
             .scope_boxed()
         })
-        .await
-        .unwrap();
+        .await;
+
+    finish_dry_run(result)?;
+    Ok(diff)
 }
 
-async fn do_hourly_update(
-    conn: &mut crate::Connection<'_>,
-    redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
-    if let Err(e) = update_ranks(conn).await {
-        error!("update_ranks failed: {e}");
+async fn get_player_stats_json(player_id: i64) -> Result<String, String> {
+    if !std::fs::exists("token.txt").unwrap_or(false) {
+        return Err("GGST is not connected, patch?".to_string());
     }
 
-    if let Err(e) = update_stats(conn, redis_connection).await {
-        error!("update_stats failed: {e}");
+    match ggst_api::get_player_stats(player_id.to_string()).await {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Failed to get player stats: {e}")),
+    }
+}
+
+/// Runs the hourly jobs once. Jobs that succeeded are kept even if others failed.
+pub async fn do_hourly_update_once(state: crate::AppState) -> Result<(), String> {
+    let mut connection = match state.db_pool.get().await {
+        Ok(connection) => connection,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut redis_connection = match state.redis_pool.get().await {
+        Ok(redis_connection) => redis_connection,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let result = connection
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move { Ok(do_hourly_update(conn, &mut redis_connection).await) }.scope_boxed()
+        })
+        .await;
+
+    match result {
+        Ok(result) => result,
+        Err(e) => Err(format!("Hourly update failed: {e}")),
+    }
+}
+
+/// Runs the daily jobs once. Jobs that succeeded are kept even if others failed.
+pub async fn do_daily_update_once(state: crate::AppState) -> Result<(), String> {
+    let mut connection = match state.db_pool.get().await {
+        Ok(connection) => connection,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut redis_connection = match state.redis_pool.get().await {
+        Ok(redis_connection) => redis_connection,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let result = connection
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move { Ok(do_daily_update(conn, &mut redis_connection).await) }.scope_boxed()
+        })
+        .await;
+
+    match result {
+        Ok(result) => result,
+        Err(e) => Err(format!("Daily update failed: {e}")),
     }
+}
 
+async fn set_last_update(
+    key: &str,
+    redis_connection: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
     //Now
     let last_update =
         chrono::DateTime::from_timestamp(chrono::Utc::now().naive_utc().and_utc().timestamp(), 0)
@@ -224,12 +580,118 @@ async fn do_hourly_update(
             .naive_utc()
             .to_string();
 
-    redis::cmd("SET")
-        .arg("last_update_hourly")
+    match redis::cmd("SET")
+        .arg(key)
         .arg(last_update)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting last_update_hourly");
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("Error setting {key}")),
+    }
+}
+
+/// Prefixes and scopes of the patches that need their summaries recomputed: the current patch,
+/// and any earlier patch that was last summarized (`last_update_key`) before it ended.
+async fn get_patch_windows(
+    last_update_key: &str,
+    conn: &mut crate::Connection<'_>,
+    redis_connection: &mut crate::RedisConnection<'_>,
+) -> Result<Vec<(String, GameScope)>, String> {
+    let patches = crate::db::get_patches(conn).await?;
+    let mut windows = vec![];
+
+    for patch in patches.iter() {
+        let prefix = crate::imdb::patch_prefix(&patch.version);
+
+        let last_update: Result<String, redis::RedisError> = redis::cmd("GET")
+            .arg(format!("{}{}", prefix, last_update_key))
+            .query_async(&mut **redis_connection)
+            .await;
+
+        let last_update = last_update
+            .ok()
+            .and_then(|lu| NaiveDateTime::parse_from_str(&lu, "%Y-%m-%d %H:%M:%S").ok());
+
+        //Closed patches only need one update after they end
+        if let Some(end) = crate::db::get_patch_end(&patches, &patch.version)
+            && last_update.is_some_and(|lu| lu >= end)
+        {
+            continue;
+        }
+
+        windows.push((prefix, GameScope::patch(&patch.version)));
+    }
+
+    Ok(windows)
+}
+
+async fn do_hourly_update(
+    conn: &mut crate::Connection<'_>,
+    redis_connection: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    //Each job runs even if an earlier one failed, the failures are reported at the end
+    let mut failed = vec![];
+
+    //Snapshot the watched players' ranks to spot who enters the top 100
+    let ranks_before = match crate::db::get_tracked_global_ranks(conn).await {
+        Ok(ranks) => Some(ranks),
+        Err(e) => {
+            error!("get_tracked_global_ranks failed: {e}");
+            failed.push("get_tracked_global_ranks".to_string());
+            None
+        }
+    };
+
+    if let Err(e) = update_ranks(conn).await {
+        error!("update_ranks failed: {e}");
+        failed.push("update_ranks".to_string());
+    }
+
+    if let Some(ranks_before) = ranks_before
+        && let Err(e) =
+            crate::webhooks::queue_top_100_events(&ranks_before, conn, redis_connection).await
+    {
+        error!("queue_top_100_events failed: {e}");
+        failed.push("queue_top_100_events".to_string());
+    }
+
+    if let Err(e) = update_stats("", &GameScope::all(), conn, redis_connection).await {
+        error!("update_stats failed: {e}");
+        failed.push("update_stats".to_string());
+    }
+
+    match get_patch_windows("last_update_hourly", conn, redis_connection).await {
+        Ok(windows) => {
+            for (prefix, range) in windows {
+                if let Err(e) = update_stats(&prefix, &range, conn, redis_connection).await {
+                    error!("update_stats {prefix} failed: {e}");
+                    failed.push(format!("update_stats {prefix}"));
+                    continue;
+                }
+                if let Err(e) =
+                    set_last_update(&format!("{}last_update_hourly", prefix), redis_connection)
+                        .await
+                {
+                    error!("set_last_update {prefix} failed: {e}");
+                    failed.push(format!("set_last_update {prefix}"));
+                }
+            }
+        }
+        Err(e) => {
+            error!("get_patch_windows failed: {e}");
+            failed.push("get_patch_windows".to_string());
+        }
+    }
+
+    if let Err(e) = set_last_update("last_update_hourly", redis_connection).await {
+        error!("set_last_update failed: {e}");
+        failed.push("set_last_update".to_string());
+    }
+
+    if !failed.is_empty() {
+        return Err(format!("{} failed", failed.join(", ")));
+    }
     Ok(())
 }
 
@@ -237,36 +699,77 @@ async fn do_daily_update(
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
 ) -> Result<(), String> {
-    if let Err(e) = update_popularity(conn, redis_connection).await {
+    //Each job runs even if an earlier one failed, the failures are reported at the end
+    let mut failed = vec![];
+
+    let one_month = GameScope::all().since(
+        Utc::now()
+            .naive_utc()
+            .checked_sub_months(Months::new(1))
+            .unwrap(),
+    );
+
+    if let Err(e) = update_popularity("", &one_month, conn, redis_connection).await {
         error!("update_popularity failed: {e}");
+        failed.push("update_popularity".to_string());
     }
 
-    if let Err(e) = update_matchups(conn, redis_connection).await {
+    if let Err(e) = update_character_daily_stats(conn).await {
+        error!("update_character_daily_stats failed: {e}");
+        failed.push("update_character_daily_stats".to_string());
+    }
+
+    if let Err(e) = update_matchups("", &one_month, conn, redis_connection).await {
         error!("update_matchups failed: {e}");
+        failed.push("update_matchups".to_string());
     }
 
     if let Err(e) = update_distribution(conn, redis_connection).await {
         error!("update_distribution failed: {e}");
+        failed.push("update_distribution".to_string());
     }
 
-    //Now
-    let last_update =
-        chrono::DateTime::from_timestamp(chrono::Utc::now().naive_utc().and_utc().timestamp(), 0)
-            .unwrap()
-            .naive_utc()
-            .to_string();
+    match get_patch_windows("last_update_daily", conn, redis_connection).await {
+        Ok(windows) => {
+            for (prefix, range) in windows {
+                if let Err(e) = update_popularity(&prefix, &range, conn, redis_connection).await {
+                    error!("update_popularity {prefix} failed: {e}");
+                    failed.push(format!("update_popularity {prefix}"));
+                    continue;
+                }
+                if let Err(e) = update_matchups(&prefix, &range, conn, redis_connection).await {
+                    error!("update_matchups {prefix} failed: {e}");
+                    failed.push(format!("update_matchups {prefix}"));
+                    continue;
+                }
+                if let Err(e) =
+                    set_last_update(&format!("{}last_update_daily", prefix), redis_connection).await
+                {
+                    error!("set_last_update {prefix} failed: {e}");
+                    failed.push(format!("set_last_update {prefix}"));
+                }
+            }
+        }
+        Err(e) => {
+            error!("get_patch_windows failed: {e}");
+            failed.push("get_patch_windows".to_string());
+        }
+    }
 
-    redis::cmd("SET")
-        .arg("last_update_daily")
-        .arg(last_update)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting last_update_daily");
+    if let Err(e) = set_last_update("last_update_daily", redis_connection).await {
+        error!("set_last_update failed: {e}");
+        failed.push("set_last_update".to_string());
+    }
 
     //Clear this so that health check doesn't fail
-    crate::imdb::clear_latest_game_time(redis_connection)
-        .await
-        .unwrap();
+    if let Err(e) = crate::imdb::clear_latest_game_time(redis_connection).await {
+        error!("clear_latest_game_time failed: {e}");
+        failed.push("clear_latest_game_time".to_string());
+    }
+
+    if !failed.is_empty() {
+        return Err(format!("{} failed", failed.join(", ")));
+    }
     Ok(())
 }
 
@@ -277,6 +780,9 @@ pub struct DistributionResult {
     pub lower_bound: i32,
     #[diesel(sql_type = diesel::sql_types::Integer)]
     pub upper_bound: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    #[serde(default)]
+    pub name: String,
     #[diesel(sql_type = diesel::sql_types::BigInt)]
     pub count: i64,
     #[diesel(sql_type = diesel::sql_types::Double)]
@@ -284,60 +790,75 @@ pub struct DistributionResult {
     #[diesel(sql_type = diesel::sql_types::Double)]
     pub percentile: f64,
 }
-async fn update_distribution(
-    conn: &mut crate::Connection<'_>,
-    redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
-    info!("Updating distribution");
 
+/// Buckets `player_ratings` into the tiers defined in `rank_tiers`.
+/// A `char_id` of -1 covers every character.
+async fn get_distribution(
+    char_id: i32,
+    conn: &mut crate::Connection<'_>,
+) -> Result<Vec<DistributionResult>, String> {
     let distribution_results = diesel::sql_query(
         "
-        WITH buckets AS (
+        WITH bucket_counts AS (  -- CTE to count values in each tier, keeping empty tiers
             SELECT
-              unnest(ARRAY[-10000000, 1,    1000, 2000, 3000, 4200, 5400, 6600, 8800,  11000, 13200, 15600, 18000, 20400, 24400, 28400, 32400, 36600, 40800, 45000]) AS lower_bound,
-              unnest(ARRAY[1, 1000, 2000, 3000, 4200, 5400, 6600, 8800, 11000, 13200, 15600, 18000, 20400, 24400, 28400, 32400, 36600, 40800, 45000, 200000000]) AS upper_bound
-        ),
-        bucket_counts AS (  -- CTE to count values in each bucket
-            SELECT 
-                b.lower_bound, 
-                b.upper_bound, 
+                b.lower_bound,
+                b.upper_bound,
+                b.name,
                 count(t.value) AS bucket_count
-            FROM player_ratings t
-            LEFT JOIN buckets b ON t.value >= b.lower_bound AND t.value < b.upper_bound
-            GROUP BY b.lower_bound, b.upper_bound
+            FROM rank_tiers b
+            LEFT JOIN player_ratings t
+                ON t.value >= b.lower_bound
+                AND t.value < b.upper_bound
+                AND ($1 = -1 OR t.char_id = $1)
+            GROUP BY b.lower_bound, b.upper_bound, b.name
         ),
         percentiles AS ( -- CTE to calculate cumulative percentage (excluding placement from percentile)
-            SELECT 
+            SELECT
                 lower_bound,
                 upper_bound,
+                name,
                 bucket_count,
-                SUM(bucket_count) OVER (ORDER BY lower_bound) as cumulative_sum,
                 SUM(bucket_count) OVER () as total_count,
-                SUM(CASE WHEN upper_bound != 1 THEN bucket_count ELSE 0 END) OVER (ORDER BY lower_bound) as cumulative_sum_ranked_only,
-                SUM(bucket_count) FILTER (WHERE upper_bound != 1) OVER () as total_count_excluding_placement
-            FROM bucket_counts        
+                SUM(CASE WHEN upper_bound > 1 THEN bucket_count ELSE 0 END) OVER (ORDER BY lower_bound) as cumulative_sum_ranked_only,
+                SUM(bucket_count) FILTER (WHERE upper_bound > 1) OVER () as total_count_excluding_placement
+            FROM bucket_counts
         )
-        SELECT 
+        SELECT
             p.lower_bound,
             p.upper_bound,
+            p.name,
             p.bucket_count AS count,
-            CASE 
-                WHEN p.upper_bound = 1 THEN CAST(ROUND((p.bucket_count * 100.0 / p.total_count), 2) AS FLOAT)  -- Placement percentage includes all players
-                ELSE CAST(ROUND((p.bucket_count * 100.0 / p.total_count_excluding_placement), 2) AS FLOAT)  -- Ranked percentage excludes placement
+            CASE
+                WHEN p.upper_bound <= 1 THEN CAST(COALESCE(ROUND((p.bucket_count * 100.0 / NULLIF(p.total_count, 0)), 2), 0) AS FLOAT)  -- Placement percentage includes all players
+                ELSE CAST(COALESCE(ROUND((p.bucket_count * 100.0 / NULLIF(p.total_count_excluding_placement, 0)), 2), 0) AS FLOAT)  -- Ranked percentage excludes placement
             END AS percentage,
-            CASE 
-                WHEN p.upper_bound = 1 THEN 0.0  -- Set percentile to 0 for placement
-                ELSE CAST(ROUND((p.cumulative_sum_ranked_only * 100.0 / p.total_count_excluding_placement), 2) AS FLOAT)
+            CASE
+                WHEN p.upper_bound <= 1 THEN 0.0  -- Set percentile to 0 for placement
+                ELSE CAST(COALESCE(ROUND((p.cumulative_sum_ranked_only * 100.0 / NULLIF(p.total_count_excluding_placement, 0)), 2), 0) AS FLOAT)
             END AS percentile
         FROM percentiles p
         ORDER BY p.lower_bound;
         ",
     );
 
-    let distribution_results = distribution_results
+    match distribution_results
+        .bind::<Integer, _>(char_id)
         .get_results::<DistributionResult>(conn)
         .await
-        .unwrap();
+    {
+        Ok(results) => Ok(results),
+        Err(e) => Err(format!("Error loading distribution: {}", e)),
+    }
+}
+
+async fn update_distribution(
+    conn: &mut crate::Connection<'_>,
+    redis_connection: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    info!("Updating distribution");
+    let _timer = crate::monitoring::JobTimer::start("update_distribution");
+
+    let distribution_results = get_distribution(-1, conn).await?;
 
     redis::cmd("SET")
         .arg("distribution_rating")
@@ -346,6 +867,17 @@ async fn update_distribution(
         .await
         .expect("Error setting distribution");
 
+    for c in characters::all().iter() {
+        let distribution_results = get_distribution(i32::from(c.id), conn).await?;
+
+        redis::cmd("SET")
+            .arg(format!("distribution_rating_{}", c.short))
+            .arg(serde_json::to_string(&distribution_results).unwrap())
+            .query_async::<String>(&mut **redis_connection)
+            .await
+            .expect("Error setting character distribution");
+    }
+
     info!("Updating distribution - Done");
     Ok(())
 }
@@ -360,16 +892,17 @@ pub struct Matchup {
     pub total_games: i64,
 }
 async fn update_matchups(
+    prefix: &str,
+    range: &GameScope,
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
 ) -> Result<(), String> {
-    info!("Updating matchups");
+    info!("Updating matchups {prefix}");
+    let _timer = crate::monitoring::JobTimer::start("update_matchups");
 
-    {
-        let mut all_characters: std::collections::HashMap<i16, Vec<Matchup>> =
-            std::collections::HashMap::new();
-
-        for c in 0..CHAR_NAMES.len() {
+    //Vanquisher matchups only count games where both players are above 10000000
+    for (name, min_value) in [("matchup", 0), ("matchup_vanq", 10000000)] {
+        for c in characters::all().iter() {
             let results = diesel::sql_query(
                 "
               SELECT 
@@ -387,103 +920,42 @@ async fn update_matchups(
                       'a' as position
                   FROM games
                   WHERE char_a = $1
-                  AND timestamp > now() - interval '1 month'
+                  AND timestamp >= $2
+                  AND timestamp < $3
                   AND game_floor = 0  -- Only ranked matches
-                  AND value_a > 0
-                  AND value_b > 0
+                  AND value_a > $4
+                  AND value_b > $4
+                  AND ($5::text IS NULL OR version = $5)
                   UNION ALL
                   SELECT 
                       char_a as opponent_char, 
-                      winner,
-                      'b' as position
-                  FROM games
-                  WHERE char_b = $1
-                  AND timestamp > now() - interval '1 month'
-                  AND game_floor = 0  -- Only ranked matches
-                  AND value_a > 0
-                  AND value_b > 0
-              ) as combined_results
-              GROUP BY opponent_char
-              ORDER BY opponent_char;
-              ",
-            );
-            let results: Vec<Matchup> = results
-                .bind::<Integer, _>(i32::try_from(c).unwrap())
-                .get_results(conn)
-                .await
-                .unwrap();
-
-            all_characters.insert(i16::try_from(c).unwrap(), results);
-        }
-
-        for (char_id, matchups) in all_characters {
-            let char_id = char_id as usize;
-
-            redis::cmd("SET")
-                .arg(format!("matchup_{}", char_id))
-                .arg(serde_json::to_string(&matchups).unwrap())
-                .query_async::<String>(&mut **redis_connection)
-                .await
-                .expect("Error setting matchup");
-        }
-    }
-
-    {
-        let mut all_characters: std::collections::HashMap<i16, Vec<Matchup>> =
-            std::collections::HashMap::new();
-
-        for c in 0..CHAR_NAMES.len() {
-            let results = diesel::sql_query(
-                "
-            SELECT 
-                opponent_char,
-                SUM(CASE 
-                    WHEN (position = 'a' AND winner = 1) OR (position = 'b' AND winner = 2)
-                    THEN 1 
-                    ELSE 0 
-                END) as wins,
-                COUNT(*) as total_games
-            FROM (
-                SELECT 
-                    char_b as opponent_char, 
-                    winner,
-                    'a' as position
-                FROM games
-                WHERE char_a = $1
-                AND timestamp > now() - interval '1 month'
-                AND game_floor = 0  -- Only ranked matches
-                AND value_a > 10000000
-                AND value_b > 10000000
-                UNION ALL
-                SELECT 
-                    char_a as opponent_char, 
-                    winner,
-                    'b' as position
-                FROM games
-                WHERE char_b = $1
-                AND timestamp > now() - interval '1 month'
-                AND game_floor = 0  -- Only ranked matches
-                AND value_a > 10000000
-                AND value_b > 10000000
-            ) as combined_results
-            GROUP BY opponent_char
-            ORDER BY opponent_char;
-            ",
+                      winner,
+                      'b' as position
+                  FROM games
+                  WHERE char_b = $1
+                  AND timestamp >= $2
+                  AND timestamp < $3
+                  AND game_floor = 0  -- Only ranked matches
+                  AND value_a > $4
+                  AND value_b > $4
+                  AND ($5::text IS NULL OR version = $5)
+              ) as combined_results
+              GROUP BY opponent_char
+              ORDER BY opponent_char;
+              ",
             );
-            let results: Vec<Matchup> = results
-                .bind::<Integer, _>(i32::try_from(c).unwrap())
+            let matchups: Vec<Matchup> = results
+                .bind::<Integer, _>(i32::from(c.id))
+                .bind::<Timestamp, _>(range.start)
+                .bind::<Timestamp, _>(range.end)
+                .bind::<BigInt, _>(min_value)
+                .bind::<Nullable<Text>, _>(&range.version)
                 .get_results(conn)
                 .await
                 .unwrap();
 
-            all_characters.insert(i16::try_from(c).unwrap(), results);
-        }
-
-        for (char_id, matchups) in all_characters {
-            let char_id = char_id as usize;
-
             redis::cmd("SET")
-                .arg(format!("matchup_vanq_{}", char_id))
+                .arg(format!("{}{}_{}", prefix, name, c.id))
                 .arg(serde_json::to_string(&matchups).unwrap())
                 .query_async::<String>(&mut **redis_connection)
                 .await
@@ -491,7 +963,7 @@ async fn update_matchups(
         }
     }
 
-    info!("Updating matchups - Done");
+    info!("Updating matchups {prefix} - Done");
     Ok(())
 }
 
@@ -502,16 +974,13 @@ struct PopularityResult {
     #[diesel(sql_type = diesel::sql_types::BigInt)]
     count: i64,
 }
-#[derive(QueryableByName)]
-struct PopularityResultTotal {
-    #[diesel(sql_type = diesel::sql_types::BigInt)]
-    count: i64,
-}
 async fn update_popularity(
+    prefix: &str,
+    range: &GameScope,
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
 ) -> Result<(), String> {
-    info!("Updating popularity");
+    info!("Updating popularity {prefix}");
     //We're using subqueries here, so we need to use sql_query
 
     //Distinct player + character combination counts
@@ -521,321 +990,261 @@ async fn update_popularity(
         FROM (
             SELECT g.char_a as c, g.id_a as id
             FROM games g
-            WHERE g.timestamp > now() - interval '1 month'
+            WHERE g.timestamp >= $1 AND g.timestamp < $2
+            AND ($3::text IS NULL OR g.version = $3)
         UNION
             SELECT g.char_b as c, g.id_b as id
             FROM games g
-            WHERE g.timestamp > now() - interval '1 month'
+            WHERE g.timestamp >= $1 AND g.timestamp < $2
+            AND ($3::text IS NULL OR g.version = $3)
         ) as combined_results
         GROUP BY c;
     ",
     );
 
-    let results: Vec<PopularityResult> =
-        results.get_results::<PopularityResult>(conn).await.unwrap();
+    let results: Vec<PopularityResult> = results
+        .bind::<Timestamp, _>(range.start)
+        .bind::<Timestamp, _>(range.end)
+        .bind::<Nullable<Text>, _>(&range.version)
+        .get_results::<PopularityResult>(conn)
+        .await
+        .unwrap();
 
-    for r in results {
-        let char_id = r.c as usize;
-        let count = r.count;
+    //Characters nobody played in this range still need a key
+    let per_player: HashMap<i16, i64> = results.into_iter().map(|r| (r.c, r.count)).collect();
 
+    for c in characters::all().iter() {
         redis::cmd("SET")
-            .arg(format!("popularity_per_player_{}", CHAR_NAMES[char_id].0))
-            .arg(count)
+            .arg(format!("{}popularity_per_player_{}", prefix, c.short))
+            .arg(per_player.get(&c.id).copied().unwrap_or(0))
             .query_async::<String>(&mut **redis_connection)
             .await
             .expect("Error setting popularity per player");
     }
 
     //Total distinct player + character combination.
-    let results_total_players = diesel::sql_query(
-        "
-        SELECT COUNT(id) as count
-        FROM (
-            SELECT g.id_a as id
-            FROM games g
-            WHERE g.timestamp > now() - interval '1 month'
-        UNION
-            SELECT g.id_b as id
-            FROM games g
-            WHERE g.timestamp > now() - interval '1 month'
-        ) as combined_results;
-        ",
-    );
-
-    let results_total_players: Vec<PopularityResultTotal> = results_total_players
-        .get_results::<PopularityResultTotal>(conn)
-        .await
-        .unwrap();
+    let results_total_players = count_players(range, conn).await?;
 
     redis::cmd("SET")
-        .arg("popularity_per_player_total")
-        .arg(results_total_players[0].count)
+        .arg(format!("{}popularity_per_player_total", prefix))
+        .arg(results_total_players)
         .query_async::<String>(&mut **redis_connection)
         .await
         .expect("Error setting popularity_total");
 
-    //Total game count per character (no total needed, we can use 'one_month_games' from stats)
+    //Total game count per character (no total needed, we can use the game count from stats)
     let results = diesel::sql_query(
         "
         SELECT c, COUNT(c) as count
         FROM (
             SELECT g.char_a as c
             FROM games g
-            WHERE g.timestamp > now() - interval '1 month'
+            WHERE g.timestamp >= $1 AND g.timestamp < $2
+            AND ($3::text IS NULL OR g.version = $3)
         UNION ALL
             SELECT g.char_b as c
             FROM games g
-            WHERE g.timestamp > now() - interval '1 month'
+            WHERE g.timestamp >= $1 AND g.timestamp < $2
+            AND ($3::text IS NULL OR g.version = $3)
         ) as combined_results
         GROUP BY c;
         ",
     );
 
-    let results: Vec<PopularityResult> =
-        results.get_results::<PopularityResult>(conn).await.unwrap();
+    let results: Vec<PopularityResult> = results
+        .bind::<Timestamp, _>(range.start)
+        .bind::<Timestamp, _>(range.end)
+        .bind::<Nullable<Text>, _>(&range.version)
+        .get_results::<PopularityResult>(conn)
+        .await
+        .unwrap();
 
-    for r in results {
-        let char_id = r.c as usize;
-        let count = r.count;
+    let per_character: HashMap<i16, i64> = results.into_iter().map(|r| (r.c, r.count)).collect();
 
+    for c in characters::all().iter() {
         redis::cmd("SET")
-            .arg(format!(
-                "popularity_per_character_{}",
-                CHAR_NAMES[char_id].0
-            ))
-            .arg(count)
+            .arg(format!("{}popularity_per_character_{}", prefix, c.short))
+            .arg(per_character.get(&c.id).copied().unwrap_or(0))
             .query_async::<String>(&mut **redis_connection)
             .await
             .expect("Error setting popularity per game");
     }
 
-    info!("Updating popularity - Done");
+    info!("Updating popularity {prefix} - Done");
     Ok(())
 }
 
-#[derive(QueryableByName, Queryable)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
-struct CountResult {
-    #[diesel(sql_type = BigInt)]
-    count: i64,
-}
-async fn update_stats(
-    conn: &mut crate::Connection<'_>,
-    redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
-    info!("Updating stats");
-
-    // Get total game count
-    let total_games: i64 = schema::games::table
-        .count()
-        .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
-
-    // Get one month game count
-    let one_month_games = schema::games::table
-        .filter(
-            schema::games::timestamp
-                .gt(chrono::Utc::now().naive_utc() - chrono::Duration::days(30)),
-        )
-        .count()
-        .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
-
-    // Get one week game count
-    let one_week_games = schema::games::table
-        .filter(
-            schema::games::timestamp.gt(chrono::Utc::now().naive_utc() - chrono::Duration::days(7)),
-        )
-        .count()
-        .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+async fn update_character_daily_stats(conn: &mut crate::Connection<'_>) -> Result<(), String> {
+    info!("Updating character daily stats");
 
-    // Get one day game count
-    let one_day_games = schema::games::table
-        .filter(
-            schema::games::timestamp.gt(chrono::Utc::now().naive_utc() - chrono::Duration::days(1)),
-        )
-        .count()
-        .get_result::<i64>(conn)
+    //Recalculate from the last stored day, it was likely only partially counted.
+    //If nothing has been stored yet, backfill from the first game.
+    let last_day = match schema::character_daily_stats::table
+        .select(max(schema::character_daily_stats::day))
+        .first::<Option<chrono::NaiveDate>>(conn)
         .await
-        .expect("Error loading games");
+    {
+        Ok(last_day) => last_day,
+        Err(e) => return Err(format!("Error loading last daily stat: {}", e)),
+    };
 
-    // Get one hour game count
-    let one_hour_games = schema::games::table
-        .filter(
-            schema::games::timestamp
-                .gt(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)),
-        )
-        .count()
-        .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+    let start = match last_day {
+        Some(day) => day.and_hms_opt(0, 0, 0).unwrap(),
+        None => {
+            let first_game = match schema::games::table
+                .select(min(schema::games::timestamp))
+                .first::<Option<NaiveDateTime>>(conn)
+                .await
+            {
+                Ok(first_game) => first_game,
+                Err(e) => return Err(format!("Error loading first game: {}", e)),
+            };
 
-    // Get total player count
-    let total_players = schema::players::table
-        .count()
-        .get_result::<i64>(conn)
-        .await
-        .expect("Error loading players");
+            match first_game {
+                Some(ts) => ts.date().and_hms_opt(0, 0, 0).unwrap(),
+                None => return Ok(()),
+            }
+        }
+    };
 
-    // Get past month player count
-    let one_month_players = diesel::sql_query(
+    let results = diesel::sql_query(
         "
-        select count(id)
-        from (
-            select id_a as id
-            from games
-            where timestamp > now() - interval '1 month'
-            union
-            select id_b as id
-            from games
-            where timestamp > now() - interval '1 month'
-        ) as combined_result;
+        INSERT INTO character_daily_stats (day, char_id, games, players)
+        SELECT day, c, COUNT(*) as games, COUNT(DISTINCT id) as players
+        FROM (
+            SELECT g.timestamp::date as day, g.char_a as c, g.id_a as id
+            FROM games g
+            WHERE g.timestamp >= $1
+        UNION ALL
+            SELECT g.timestamp::date as day, g.char_b as c, g.id_b as id
+            FROM games g
+            WHERE g.timestamp >= $1
+        ) as combined_results
+        GROUP BY day, c
+        ON CONFLICT (day, char_id) DO UPDATE
+        SET games = EXCLUDED.games, players = EXCLUDED.players;
         ",
     );
-    let one_month_players = one_month_players
-        .get_results::<CountResult>(conn)
-        .await
-        .unwrap();
-    let one_month_players = one_month_players[0].count;
 
-    // Get past month player count
-    let one_week_players = diesel::sql_query(
-        "
-        select count(id)
-        from (
-            select id_a as id
-            from games
-            where timestamp > now() - interval '1 week'
-            union
-            select id_b as id
-            from games
-            where timestamp > now() - interval '1 week'
-        ) as combined_result;
-        ",
-    );
-    let one_week_players = one_week_players
-        .get_results::<CountResult>(conn)
+    let count = match results
+        .bind::<diesel::sql_types::Timestamp, _>(start)
+        .execute(conn)
         .await
-        .unwrap();
-    let one_week_players = one_week_players[0].count;
+    {
+        Ok(count) => count,
+        Err(e) => return Err(format!("Error updating character daily stats: {}", e)),
+    };
 
-    // Get past day player count
-    let one_day_players = diesel::sql_query(
-        "
-        select count(id)
-        from (
-            select id_a as id
-            from games
-            where timestamp > now() - interval '1 day'
-            union
-            select id_b as id
-            from games
-            where timestamp > now() - interval '1 day'
-        ) as combined_result;
-        ",
-    );
-    let one_day_players = one_day_players
-        .get_results::<CountResult>(conn)
+    info!("Updated {} character daily stats since {}", count, start.date());
+
+    info!("Updating character daily stats - Done");
+    Ok(())
+}
+
+#[derive(QueryableByName, Queryable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct CountResult {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+async fn count_games(range: &GameScope, conn: &mut crate::Connection<'_>) -> Result<i64, String> {
+    match schema::games::table
+        .filter(range.filter())
+        .count()
+        .get_result::<i64>(conn)
         .await
-        .unwrap();
-    let one_day_players = one_day_players[0].count;
+    {
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Error loading games: {e}")),
+    }
+}
 
-    // Get past hour player count
-    let one_hour_players = diesel::sql_query(
+async fn count_players(range: &GameScope, conn: &mut crate::Connection<'_>) -> Result<i64, String> {
+    let results = diesel::sql_query(
         "
         select count(id)
         from (
             select id_a as id
             from games
-            where timestamp > now() - interval '1 hour'
+            where timestamp >= $1 and timestamp < $2
+            and ($3::text is null or version = $3)
             union
             select id_b as id
             from games
-            where timestamp > now() - interval '1 hour'
+            where timestamp >= $1 and timestamp < $2
+            and ($3::text is null or version = $3)
         ) as combined_result;
         ",
-    );
-    let one_hour_players = one_hour_players
-        .get_results::<CountResult>(conn)
-        .await
-        .unwrap();
-    let one_hour_players = one_hour_players[0].count;
-
-    redis::cmd("SET")
-        .arg("total_games")
-        .arg(total_games)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting total_games");
-
-    redis::cmd("SET")
-        .arg("one_month_games")
-        .arg(one_month_games)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_month_games");
-
-    redis::cmd("SET")
-        .arg("one_week_games")
-        .arg(one_week_games)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_week_games");
-
-    redis::cmd("SET")
-        .arg("one_day_games")
-        .arg(one_day_games)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_day_games");
-
-    redis::cmd("SET")
-        .arg("one_hour_games")
-        .arg(one_hour_games)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_hour_games");
-
-    redis::cmd("SET")
-        .arg("total_players")
-        .arg(total_players)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting total_players");
-
-    redis::cmd("SET")
-        .arg("one_month_players")
-        .arg(one_month_players)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_month_players");
-
-    redis::cmd("SET")
-        .arg("one_week_players")
-        .arg(one_week_players)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_week_players");
-
-    redis::cmd("SET")
-        .arg("one_day_players")
-        .arg(one_day_players)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_day_players");
+    )
+    .bind::<Timestamp, _>(range.start)
+    .bind::<Timestamp, _>(range.end)
+    .bind::<Nullable<Text>, _>(&range.version)
+    .get_results::<CountResult>(conn)
+    .await;
+
+    match results {
+        Ok(results) => Ok(results[0].count),
+        Err(e) => Err(format!("Error loading players: {e}")),
+    }
+}
 
-    redis::cmd("SET")
-        .arg("one_hour_players")
-        .arg(one_hour_players)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting one_hour_players");
+async fn update_stats(
+    prefix: &str,
+    range: &GameScope,
+    conn: &mut crate::Connection<'_>,
+    redis_connection: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    info!("Updating stats {prefix}");
+
+    let current_time = Utc::now().naive_utc();
+    let one_month = range.since(current_time.checked_sub_months(Months::new(1)).unwrap());
+    let one_week = range.since(current_time - chrono::Duration::days(7));
+    let one_day = range.since(current_time - chrono::Duration::days(1));
+    let one_hour = range.since(current_time - chrono::Duration::hours(1));
+
+    let total_games = count_games(range, conn).await?;
+    let one_month_games = count_games(&one_month, conn).await?;
+    let one_week_games = count_games(&one_week, conn).await?;
+    let one_day_games = count_games(&one_day, conn).await?;
+    let one_hour_games = count_games(&one_hour, conn).await?;
+
+    //Every player we know of overall, only the ones seen during the patch otherwise
+    let total_players = if prefix.is_empty() {
+        schema::players::table
+            .count()
+            .get_result::<i64>(conn)
+            .await
+            .expect("Error loading players")
+    } else {
+        count_players(range, conn).await?
+    };
+    let one_month_players = count_players(&one_month, conn).await?;
+    let one_week_players = count_players(&one_week, conn).await?;
+    let one_day_players = count_players(&one_day, conn).await?;
+    let one_hour_players = count_players(&one_hour, conn).await?;
+
+    for (key, value) in [
+        ("total_games", total_games),
+        ("one_month_games", one_month_games),
+        ("one_week_games", one_week_games),
+        ("one_day_games", one_day_games),
+        ("one_hour_games", one_hour_games),
+        ("total_players", total_players),
+        ("one_month_players", one_month_players),
+        ("one_week_players", one_week_players),
+        ("one_day_players", one_day_players),
+        ("one_hour_players", one_hour_players),
+    ] {
+        redis::cmd("SET")
+            .arg(format!("{}{}", prefix, key))
+            .arg(value)
+            .query_async::<String>(&mut **redis_connection)
+            .await
+            .unwrap_or_else(|_| panic!("Error setting {}", key));
+    }
 
-    info!("Updating stats - Done");
+    info!("Updating stats {prefix} - Done");
     Ok(())
 }
 
@@ -849,8 +1258,11 @@ struct InsertedRankRowId {
 }
 async fn update_ranks(connection: &mut AsyncPgConnection) -> Result<(), String> {
     info!("Updating ranks");
+    let _timer = crate::monitoring::JobTimer::start("update_ranks");
     //TODO change this to use Redis?
 
+    let rank_limit = crate::config::get().pull.rank_limit;
+
     //Delete all rows in the global_ranks and character_ranks tables
     diesel::delete(global_ranks::table)
         .execute(connection)
@@ -883,15 +1295,19 @@ async fn update_ranks(connection: &mut AsyncPgConnection) -> Result<(), String>
           r.rn = 1
         ORDER BY
           r.value DESC
-        LIMIT 1000
+        LIMIT $1
         RETURNING rank
     ",
     );
-    let results: Vec<InsertedRankRowId> = results.get_results(connection).await.unwrap();
+    let results: Vec<InsertedRankRowId> = results
+        .bind::<BigInt, _>(rank_limit)
+        .get_results(connection)
+        .await
+        .unwrap();
 
     info!("Inserted {} rows into global_ranks", results.len());
 
-    for c in 0..CHAR_NAMES.len() {
+    for c in characters::all().iter() {
         let results = sql_query(
             "
             INSERT INTO character_ranks (rank, id, char_id)
@@ -901,12 +1317,13 @@ async fn update_ranks(connection: &mut AsyncPgConnection) -> Result<(), String>
             WHERE r.id = p.id
             AND char_id = $1
             ORDER BY value DESC
-            LIMIT 1000
+            LIMIT $2
             RETURNING rank
         ",
         );
         let results: Vec<InsertedRankRowId> = results
-            .bind::<Integer, _>(i32::try_from(c).unwrap())
+            .bind::<Integer, _>(i32::from(c.id))
+            .bind::<BigInt, _>(rank_limit)
             .get_results(connection)
             .await
             .unwrap();
@@ -914,7 +1331,7 @@ async fn update_ranks(connection: &mut AsyncPgConnection) -> Result<(), String>
         info!(
             "Inserted {} rows into character_ranks for character {}",
             results.len(),
-            CHAR_NAMES[c].1
+            c.name
         );
     }
 
@@ -922,6 +1339,23 @@ async fn update_ranks(connection: &mut AsyncPgConnection) -> Result<(), String>
     Ok(())
 }
 
+/// Stores personal bests, tier changes and other milestones reached in newly pulled games.
+/// `previous` holds the players' rating states from the games stored before these.
+async fn record_events(
+    games: &[Game],
+    previous: &HashMap<(i64, i16), RatingState>,
+    tiers: &[RankTier],
+    conn: &mut AsyncPgConnection,
+) -> Result<(), String> {
+    let events = crate::handlers::events::detect_events(games, previous, tiers);
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    info!("Recording {} events", events.len());
+    crate::db::insert_events(&events, conn).await
+}
+
 async fn update_player_info(
     connection: &mut AsyncPgConnection,
     new_game: &Game,
@@ -1014,6 +1448,185 @@ async fn update_player_info(
     Ok(())
 }
 
+/// Adds a newly inserted game to both players' `player_char_stats` rows.
+/// Only call this once per game, replays that were already stored would be counted twice.
+async fn update_player_char_stats(
+    connection: &mut AsyncPgConnection,
+    new_game: &Game,
+) -> Result<(), String> {
+    //Ties keep the earlier peak and top defeated, same as `rebuild_player_char_stats`
+    for stat in crate::handlers::player::game_char_stats(new_game) {
+        if let Err(e) = diesel::sql_query(
+            "
+            INSERT INTO player_char_stats AS s (
+                id, char_id, games, wins, losses, peak_rating, peak_timestamp,
+                top_defeated_id, top_defeated_name, top_defeated_char, top_defeated_value,
+                top_defeated_timestamp, first_played, last_played
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            ON CONFLICT (id, char_id) DO UPDATE SET
+                games = s.games + EXCLUDED.games,
+                wins = s.wins + EXCLUDED.wins,
+                losses = s.losses + EXCLUDED.losses,
+                peak_rating = GREATEST(s.peak_rating, EXCLUDED.peak_rating),
+                peak_timestamp = CASE WHEN EXCLUDED.peak_rating > s.peak_rating
+                    THEN EXCLUDED.peak_timestamp ELSE s.peak_timestamp END,
+                top_defeated_id = CASE WHEN EXCLUDED.top_defeated_value > COALESCE(s.top_defeated_value, -1)
+                    THEN EXCLUDED.top_defeated_id ELSE s.top_defeated_id END,
+                top_defeated_name = CASE WHEN EXCLUDED.top_defeated_value > COALESCE(s.top_defeated_value, -1)
+                    THEN EXCLUDED.top_defeated_name ELSE s.top_defeated_name END,
+                top_defeated_char = CASE WHEN EXCLUDED.top_defeated_value > COALESCE(s.top_defeated_value, -1)
+                    THEN EXCLUDED.top_defeated_char ELSE s.top_defeated_char END,
+                top_defeated_value = CASE WHEN EXCLUDED.top_defeated_value > COALESCE(s.top_defeated_value, -1)
+                    THEN EXCLUDED.top_defeated_value ELSE s.top_defeated_value END,
+                top_defeated_timestamp = CASE WHEN EXCLUDED.top_defeated_value > COALESCE(s.top_defeated_value, -1)
+                    THEN EXCLUDED.top_defeated_timestamp ELSE s.top_defeated_timestamp END,
+                first_played = LEAST(s.first_played, EXCLUDED.first_played),
+                last_played = GREATEST(s.last_played, EXCLUDED.last_played)
+            ",
+        )
+        .bind::<BigInt, _>(stat.id)
+        .bind::<SmallInt, _>(stat.char_id)
+        .bind::<BigInt, _>(stat.games)
+        .bind::<BigInt, _>(stat.wins)
+        .bind::<BigInt, _>(stat.losses)
+        .bind::<BigInt, _>(stat.peak_rating)
+        .bind::<Timestamp, _>(stat.peak_timestamp)
+        .bind::<Nullable<BigInt>, _>(stat.top_defeated_id)
+        .bind::<Nullable<Text>, _>(stat.top_defeated_name)
+        .bind::<Nullable<SmallInt>, _>(stat.top_defeated_char)
+        .bind::<Nullable<BigInt>, _>(stat.top_defeated_value)
+        .bind::<Nullable<Timestamp>, _>(stat.top_defeated_timestamp)
+        .bind::<Timestamp, _>(stat.first_played)
+        .bind::<Timestamp, _>(stat.last_played)
+        .execute(connection)
+        .await
+        {
+            return Err(format!("Failed to update player char stats: {e}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Recomputes `player_char_stats` from every stored game.
+pub async fn rebuild_player_char_stats(state: crate::AppState, dry_run: bool) -> Result<(), String> {
+    let mut connection = match state.db_pool.get().await {
+        Ok(connection) => connection,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let result = connection
+        .transaction::<_, Rollback, _>(|conn| {
+            async move {
+                info!("Rebuilding player char stats");
+
+                diesel::sql_query("DELETE FROM player_char_stats")
+                    .execute(conn)
+                    .await?;
+
+                let count = diesel::sql_query(
+                    "
+                    INSERT INTO player_char_stats (
+                        id, char_id, games, wins, losses, peak_rating, peak_timestamp,
+                        top_defeated_id, top_defeated_name, top_defeated_char, top_defeated_value,
+                        top_defeated_timestamp, first_played, last_played
+                    )
+                    SELECT
+                        id,
+                        char_id,
+                        count(*),
+                        count(*) FILTER (WHERE won),
+                        count(*) FILTER (WHERE NOT won),
+                        max(value),
+                        (array_agg(timestamp ORDER BY value DESC, timestamp ASC))[1],
+                        (array_agg(opponent_id ORDER BY opponent_value DESC, timestamp ASC) FILTER (WHERE won))[1],
+                        (array_agg(opponent_name ORDER BY opponent_value DESC, timestamp ASC) FILTER (WHERE won))[1],
+                        (array_agg(opponent_char ORDER BY opponent_value DESC, timestamp ASC) FILTER (WHERE won))[1],
+                        max(opponent_value) FILTER (WHERE won),
+                        (array_agg(timestamp ORDER BY opponent_value DESC, timestamp ASC) FILTER (WHERE won))[1],
+                        min(timestamp),
+                        max(timestamp)
+                    FROM (
+                        SELECT timestamp, id_a as id, char_a as char_id, value_a as value, winner = 1 as won,
+                            id_b as opponent_id, name_b as opponent_name, char_b as opponent_char, value_b as opponent_value
+                        FROM games
+                        UNION ALL
+                        SELECT timestamp, id_b as id, char_b as char_id, value_b as value, winner = 2 as won,
+                            id_a as opponent_id, name_a as opponent_name, char_a as opponent_char, value_a as opponent_value
+                        FROM games
+                    ) as combined_results
+                    GROUP BY id, char_id
+                    ",
+                )
+                .execute(conn)
+                .await?;
+
+                info!("Rebuilt {count} player char stats");
+
+                if dry_run {
+                    return Err(Rollback::DryRun);
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await;
+
+    finish_dry_run(result)
+}
+
+/// Recomputes the rankings outside of the hourly update.
+pub async fn rebuild_ranks_once(state: crate::AppState, dry_run: bool) -> Result<(), String> {
+    let mut connection = match state.db_pool.get().await {
+        Ok(connection) => connection,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let result = connection
+        .transaction::<_, Rollback, _>(|conn| {
+            async move {
+                if let Err(e) = update_ranks(conn).await {
+                    return Err(Rollback::Failed(format!("update_ranks failed: {e}")));
+                }
+
+                if dry_run {
+                    return Err(Rollback::DryRun);
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await;
+
+    finish_dry_run(result)
+}
+
+/// Why a transaction that can be a dry run was rolled back.
+enum Rollback {
+    /// The dry run finished, its changes are thrown away on purpose.
+    DryRun,
+    Failed(String),
+}
+
+impl From<diesel::result::Error> for Rollback {
+    fn from(e: diesel::result::Error) -> Self {
+        Rollback::Failed(e.to_string())
+    }
+}
+
+/// Dry runs do all the work in a transaction and roll it back at the end.
+fn finish_dry_run(result: Result<(), Rollback>) -> Result<(), String> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(Rollback::DryRun) => {
+            info!("Dry run, changes rolled back");
+            Ok(())
+        }
+        Err(Rollback::Failed(e)) => Err(e),
+    }
+}
+
 async fn grab_games(
     connection: &mut AsyncPgConnection,
     redis_connection: &mut crate::RedisConnection<'_>,
@@ -1031,9 +1644,43 @@ async fn grab_games(
 
     let num_replays = replays.len();
     info!("Got {num_replays} replays.");
+    metrics::counter!("replays_fetched_total").increment(num_replays as u64);
 
     replays.reverse();
 
+    //New characters show up in replays before anyone adds them to the registry
+    let char_ids: HashSet<i16> = replays
+        .iter()
+        .flat_map(|r| [r.player1_character, r.player2_character])
+        .filter_map(|c| i16::try_from(c).ok())
+        .collect();
+    if let Err(e) = characters::register_unknown(&char_ids, connection).await {
+        error!("register_unknown failed: {e}");
+    }
+
+    //Games are tagged with the version we query the API as, record when it was first seen
+    if let Err(e) = insert_into(patches::table)
+        .values(Patch {
+            version: crate::config::get().ggst.api_version.clone(),
+            start_date: chrono::SubsecRound::round_subsecs(Utc::now().naive_utc(), 0),
+            label: crate::config::get().ggst.api_version.clone(),
+        })
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .await
+    {
+        return Err(format!("Error inserting patch: {e}"));
+    }
+
+    //Replays arrive out of order, so events compare against every game stored before this batch
+    let ids: HashSet<i64> = replays
+        .iter()
+        .flat_map(|r| [&r.player1.id, &r.player2.id])
+        .filter_map(|id| id.parse::<i64>().ok())
+        .collect();
+    let previous = crate::db::get_rating_states(ids.into_iter().collect(), connection).await?;
+    let tiers = crate::db::get_rank_tiers(connection).await?;
+
     let mut new_games = Vec::new();
 
     //Try to keep order if possible
@@ -1072,6 +1719,7 @@ async fn grab_games(
             game_floor: i16::try_from(r.floor).ok().unwrap(),
             value_a: r.player1.rating,
             value_b: r.player2.rating,
+            version: Some(crate::config::get().ggst.api_version.clone()),
         };
 
         if let Err(e) = update_player_info(connection, &new_game).await {
@@ -1086,6 +1734,12 @@ async fn grab_games(
             .unwrap();
 
         if count > 0 {
+            //A failed statement aborts the transaction, roll back the batch rather than
+            //carry on with errors
+            if let Err(e) = update_player_char_stats(connection, &new_game).await {
+                return Err(format!("update_player_char_stats failed: {e}"));
+            }
+
             if fixed_timestamp.is_some() {
                 seconds_offset += 1;
                 debug!(
@@ -1101,6 +1755,11 @@ async fn grab_games(
         }
     }
 
+    //Recorded with the games, a failure rolls back the batch instead of losing its events
+    if let Err(e) = record_events(&new_games, &previous, &tiers, connection).await {
+        return Err(format!("record_events failed: {e}"));
+    }
+
     //Set set_latest_game_time for health check
     if let Some(last_game) = new_games.last() {
         let ts = last_game.real_timestamp.unwrap_or(last_game.timestamp);
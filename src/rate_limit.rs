@@ -0,0 +1,67 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use metrics::counter;
+use tracing::warn;
+
+use crate::handlers::rate_limit::{client_key, route_group, too_many_requests};
+use crate::imdb;
+
+/// Rejects clients that used up their bucket for the route's group with a 429.
+/// Requests are let through when Redis is unavailable, limiting isn't worth an outage.
+pub async fn limit_requests(
+    State(state): State<crate::AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &crate::config::get().rate_limit;
+    if !config.enabled {
+        return next.run(request).await;
+    }
+
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str(),
+        None => return next.run(request).await,
+    };
+
+    let (group_name, group) = match route_group(config, route) {
+        Some(group) => group,
+        None => return next.run(request).await,
+    };
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip());
+    let client = match client_key(config, request.headers(), peer) {
+        Some(client) => client,
+        None => return next.run(request).await,
+    };
+
+    let mut redis = match state.redis_pool.get().await {
+        Ok(redis) => redis,
+        Err(e) => {
+            warn!("Rate limit skipped: {e}");
+            return next.run(request).await;
+        }
+    };
+
+    let key = format!("{group_name}:{client}");
+    match imdb::take_rate_limit_token(&key, group.capacity, group.refill_per_minute, &mut redis)
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(retry_after)) => {
+            counter!("rate_limited_total", "group" => group_name.to_string()).increment(1);
+            return too_many_requests(retry_after);
+        }
+        Err(e) => warn!("{e}"),
+    }
+    drop(redis);
+
+    next.run(request).await
+}
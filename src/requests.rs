@@ -6,10 +6,6 @@ use tokio::sync::Mutex;
 use lazy_static::lazy_static;
 
 lazy_static! {
-    static ref STEAM_ID: String = dotenv::var("STEAM_ID").expect("STEAM_ID must be set.");
-    static ref STEAM_HEX: String = dotenv::var("STEAM_HEX").expect("STEAM_HEX must be set.");
-    static ref VERSION: String = dotenv::var("API_VERSION").expect("API_VERSION must be set.");
-    static ref PLAYER_ID: String = dotenv::var("PLAYER_ID").expect("PLAYER_ID must be set.");
     pub static ref STEAM_TOKEN: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(Option::None));
 }
 
@@ -68,10 +64,10 @@ pub struct PlayerStatsRequest {
 pub fn generate_player_stats_request(player_id: String) -> Request<PlayerStatsRequest> {
     Request {
         header: RequestHeader {
-            player_id: PLAYER_ID.to_owned(),
+            player_id: crate::config::get().ggst.player_id.clone(),
             token: std::fs::read_to_string("token.txt").unwrap(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: crate::config::get().ggst.api_version.clone(),
             platform: 3, //PC
         },
         body: PlayerStatsRequest {
@@ -94,10 +90,10 @@ pub struct PlayerAvatarRequest {
 pub fn generate_player_avatar_request(player_id: String) -> Request<PlayerAvatarRequest> {
     Request {
         header: RequestHeader {
-            player_id: PLAYER_ID.to_owned(),
+            player_id: crate::config::get().ggst.player_id.clone(),
             token: std::fs::read_to_string("token.txt").unwrap(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: crate::config::get().ggst.api_version.clone(),
             platform: 3, //PC
         },
         body: PlayerAvatarRequest {
@@ -114,10 +110,10 @@ pub fn generate_replay_request(
 ) -> Request<ReplayRequest> {
     Request {
         header: RequestHeader {
-            player_id: PLAYER_ID.to_owned(),
+            player_id: crate::config::get().ggst.player_id.clone(),
             token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: crate::config::get().ggst.api_version.clone(),
             platform: 3, //PC
         },
         body: ReplayRequest {
@@ -189,13 +185,13 @@ pub async fn generate_login_request() -> Request<LoginRequest> {
                     player_id: "".to_owned(),
                     token: "".to_owned(),
                     int1: 2,
-                    version: VERSION.to_owned(),
+                    version: crate::config::get().ggst.api_version.clone(),
                     platform: 3,
                 },
                 body: LoginRequest {
                     int1: 1,
-                    steam_id: STEAM_ID.to_owned(),
-                    steam_hex: STEAM_HEX.to_owned(),
+                    steam_id: crate::config::get().ggst.steam_id.clone(),
+                    steam_hex: crate::config::get().ggst.steam_hex.clone(),
                     int2: 256,
                     steam_token,
                 },
@@ -1,5 +1,14 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    character_daily_stats (day, char_id) {
+        day -> Date,
+        char_id -> Int2,
+        games -> Int8,
+        players -> Int8,
+    }
+}
+
 diesel::table! {
     character_ranks (rank, char_id) {
         id -> Int8,
@@ -8,6 +17,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    characters (id) {
+        id -> Int2,
+        short -> Text,
+        name -> Text,
+        json_code -> Text,
+        release_date -> Nullable<Date>,
+    }
+}
+
+diesel::table! {
+    events (id) {
+        id -> Int8,
+        player_id -> Int8,
+        char_id -> Int2,
+        kind -> Text,
+        value -> Int8,
+        previous_value -> Nullable<Int8>,
+        tier -> Nullable<Text>,
+        timestamp -> Timestamp,
+    }
+}
+
 diesel::table! {
     games (timestamp, id_a, id_b) {
         timestamp -> Timestamp,
@@ -24,6 +56,7 @@ diesel::table! {
         game_floor -> Int2,
         value_a -> Int8,
         value_b -> Int8,
+        version -> Nullable<Text>,
     }
 }
 
@@ -35,6 +68,33 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    patches (version) {
+        version -> Text,
+        start_date -> Timestamp,
+        label -> Text,
+    }
+}
+
+diesel::table! {
+    player_char_stats (id, char_id) {
+        id -> Int8,
+        char_id -> Int2,
+        games -> Int8,
+        wins -> Int8,
+        losses -> Int8,
+        peak_rating -> Int8,
+        peak_timestamp -> Timestamp,
+        top_defeated_id -> Nullable<Int8>,
+        top_defeated_name -> Nullable<Text>,
+        top_defeated_char -> Nullable<Int2>,
+        top_defeated_value -> Nullable<Int8>,
+        top_defeated_timestamp -> Nullable<Timestamp>,
+        first_played -> Timestamp,
+        last_played -> Timestamp,
+    }
+}
+
 diesel::table! {
     player_names (id, name) {
         id -> Int8,
@@ -42,6 +102,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    player_official_stats (id, char_id) {
+        id -> Int8,
+        char_id -> Int2,
+        level -> Nullable<Int4>,
+        ranked_games -> Nullable<Int4>,
+        ranked_wins -> Nullable<Int4>,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     player_ratings (id, char_id) {
         id -> Int8,
@@ -60,6 +131,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    rank_tiers (lower_bound) {
+        lower_bound -> Int4,
+        upper_bound -> Int4,
+        name -> Text,
+    }
+}
+
 diesel::table! {
     tags (id) {
         id -> Int4,
@@ -69,17 +148,41 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhooks (id) {
+        id -> Int4,
+        owner_id -> Int8,
+        target_id -> Int8,
+        url -> Text,
+        on_game -> Bool,
+        on_rating_high -> Bool,
+        on_top_100 -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::joinable!(character_ranks -> players (id));
+diesel::joinable!(events -> players (player_id));
 diesel::joinable!(global_ranks -> players (id));
+diesel::joinable!(player_char_stats -> players (id));
 diesel::joinable!(player_names -> players (id));
+diesel::joinable!(player_official_stats -> players (id));
 diesel::joinable!(player_ratings -> players (id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    character_daily_stats,
     character_ranks,
+    characters,
+    events,
     games,
     global_ranks,
+    patches,
+    player_char_stats,
     player_names,
+    player_official_stats,
     player_ratings,
     players,
+    rank_tiers,
     tags,
+    webhooks,
 );
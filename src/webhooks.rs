@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{http::StatusCode, response::Json, routing::post, Router};
+use chrono::Utc;
+use futures_util::{stream, StreamExt};
+use reqwest::{dns, header, redirect};
+use tracing::{info, warn};
+
+use crate::db::{self, TrackedRank};
+use crate::handlers::webhooks::{
+    game_events, is_public_address, retry_delay, top_100_events, WebhookDelivery, WebhookEvent,
+};
+use crate::imdb;
+use crate::models::Game;
+
+/// Queues game and rating high events for watched players in newly pulled games.
+pub async fn queue_game_events(
+    games: &[Game],
+    db: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    let ids: HashSet<i64> = games.iter().flat_map(|g| [g.id_a, g.id_b]).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let webhooks = db::get_webhooks_for_targets(ids.into_iter().collect(), db).await?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let watched: HashSet<i64> = webhooks.iter().map(|w| w.target_id).collect();
+    let before = games.iter().map(|g| g.timestamp).min().unwrap();
+    let highs = db::get_rating_highs(watched.into_iter().collect(), before, db).await?;
+
+    queue(game_events(games, &webhooks, &highs), redis).await
+}
+
+/// Queues top 100 events by comparing the watched players' ranks with `before`,
+/// taken ahead of the rank update.
+pub async fn queue_top_100_events(
+    before: &[TrackedRank],
+    db: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    let after = db::get_tracked_global_ranks(db).await?;
+    if after.is_empty() {
+        return Ok(());
+    }
+
+    let webhooks = db::get_webhooks_for_targets(after.iter().map(|r| r.id).collect(), db).await?;
+
+    let timestamp = chrono::DateTime::from_timestamp(Utc::now().timestamp(), 0)
+        .unwrap()
+        .naive_utc()
+        .to_string();
+
+    queue(top_100_events(before, &after, &webhooks, timestamp), redis).await
+}
+
+async fn queue(
+    deliveries: Vec<WebhookDelivery>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    let now = Utc::now().timestamp();
+
+    for delivery in &deliveries {
+        imdb::queue_webhook(delivery, now, redis).await?;
+    }
+
+    if !deliveries.is_empty() {
+        info!("Queued {} webhook deliveries", deliveries.len());
+    }
+
+    Ok(())
+}
+
+/// Deliveries sent at once, each one can wait on its url for the whole client timeout.
+const DELIVERY_CONCURRENCY: usize = 10;
+
+/// Sends every delivery that is due. Failed ones go back in the queue with a backoff
+/// until they run out of attempts.
+pub async fn deliver_due(
+    client: &reqwest::Client,
+    db: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), String> {
+    let now = Utc::now().timestamp();
+
+    let due = imdb::take_due_webhooks(now, 100, redis).await?;
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    //Deliveries carry their own copy of the url, drop the ones whose webhook was deleted
+    let ids = due.iter().map(|d| d.webhook_id).collect();
+    let existing = match db::get_existing_webhook_ids(ids, db).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            for delivery in &due {
+                imdb::queue_webhook(delivery, now, redis).await?;
+            }
+            return Err(e);
+        }
+    };
+
+    let results: Vec<(WebhookDelivery, bool)> = stream::iter(due)
+        .filter(|d| std::future::ready(existing.contains(&d.webhook_id)))
+        .map(|delivery| async move {
+            let sent = send(client, &delivery).await;
+            (delivery, sent)
+        })
+        .buffer_unordered(DELIVERY_CONCURRENCY)
+        .collect()
+        .await;
+
+    for (mut delivery, sent) in results {
+        if sent {
+            continue;
+        }
+
+        delivery.attempts += 1;
+
+        match retry_delay(delivery.attempts) {
+            Some(delay) => imdb::queue_webhook(&delivery, now + delay, redis).await?,
+            None => warn!(
+                "Giving up on webhook {} ({}) after {} attempts",
+                delivery.webhook_id, delivery.url, delivery.attempts
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(client: &reqwest::Client, delivery: &WebhookDelivery) -> bool {
+    if let Err(e) = check_webhook_url(&delivery.url).await {
+        warn!("Not delivering webhook {}: {e}", delivery.webhook_id);
+        return false;
+    }
+
+    match client
+        .post(&delivery.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&delivery.event).unwrap())
+        .send()
+        .await
+    {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+/// Resolves the webhook's host and checks every address it points to is public.
+pub async fn check_webhook_url(url: &str) -> Result<(), String> {
+    let url = match reqwest::Url::parse(url) {
+        Ok(url) => url,
+        Err(e) => return Err(format!("Webhook url is invalid: {e}")),
+    };
+
+    //IPv6 hosts keep their brackets in the url
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return Err("Webhook url has no host".to_string()),
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let addresses: Vec<SocketAddr> = match tokio::net::lookup_host((host, port)).await {
+        Ok(addresses) => addresses.collect(),
+        Err(e) => return Err(format!("Couldn't resolve webhook host {host}: {e}")),
+    };
+
+    if addresses.is_empty() || addresses.iter().any(|a| !is_public_address(a.ip())) {
+        return Err(format!("Webhook host {host} isn't a public address"));
+    }
+
+    Ok(())
+}
+
+/// Drops non-public addresses when the delivery client resolves a host, so a host can't
+/// start pointing somewhere private between `check_webhook_url` and the request.
+struct PublicResolver;
+
+impl dns::Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> dns::Resolving {
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| is_public_address(a.ip()))
+                .collect();
+
+            if addresses.is_empty() {
+                return Err(format!("{} has no public addresses", name.as_str()).into());
+            }
+
+            Ok(Box::new(addresses.into_iter()) as dns::Addrs)
+        })
+    }
+}
+
+pub fn delivery_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .unwrap()
+}
+
+/// Logs every webhook it receives, for trying out subscriptions locally.
+pub async fn run_test_receiver(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let app = Router::new()
+        .route("/", post(receive))
+        .route("/*path", post(receive));
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Webhook test receiver listening on {addr}");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn receive(Json(event): Json<WebhookEvent>) -> StatusCode {
+    info!(
+        "Received {} for {} ({}): {}",
+        event.event, event.player_name, event.player_id, event.content
+    );
+
+    StatusCode::NO_CONTENT
+}